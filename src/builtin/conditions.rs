@@ -1,7 +1,8 @@
 use std::fmt;
+use std::cmp::Ordering;
 use lval::LVal;
 use lenv::LEnv;
-use eval::eval;
+use builtin::math::compare;
 
 
 enum OrderingType {
@@ -50,22 +51,21 @@ pub fn builtin_ord(ord: OrderingType, args: Vec<LVal>) -> LVal {
     builtin_assert!(ord; args[*] is number);
 
     let mut result = true;
-    let mut x = *args[0].as_num();
 
-    for arg in args {
-        let y = arg.into_num();
+    for pair in args.windows(2) {
+        let ordering = compare(&pair[0], &pair[1]);
 
         match ord {
-            Less         => { result &= x <  y; },
-            LessEqual    => { result &= x <= y; },
-            GreaterEqual => { result &= x >= y; },
-            Greater      => { result &= x >  y; }
+            Less         => { result &= ordering == Some(Ordering::Less); },
+            LessEqual    => { result &= ordering == Some(Ordering::Less)
+                                     || ordering == Some(Ordering::Equal); },
+            GreaterEqual => { result &= ordering == Some(Ordering::Greater)
+                                     || ordering == Some(Ordering::Equal); },
+            Greater      => { result &= ordering == Some(Ordering::Greater); }
         }
-
-        x = y;
     }
 
-    LVal::Num(result as f64)
+    LVal::Bool(result)
 }
 
 
@@ -102,24 +102,33 @@ pub fn builtin_cmp(cmp: CmpType, args: Vec<LVal>) -> LVal {
     let ref o1 = args[0];
     let ref o2 = args[1];
 
+    // Compare numbers by value, so `1` and `1.0` are equal
+    if lval_is!(*o1, number) && lval_is!(*o2, number) {
+        let equal = compare(o1, o2) == Some(Ordering::Equal);
+
+        return match cmp {
+            Eq  => LVal::Bool(equal),
+            Neq => LVal::Bool(!equal)
+        }
+    }
+
     match cmp {
-        Eq  => LVal::Num((o1 == o2) as f64),
-        Neq => LVal::Num((o1 != o2) as f64),
+        Eq  => LVal::Bool(o1 == o2),
+        Neq => LVal::Bool(o1 != o2),
     }
 }
 
 
-pub fn builtin_if(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+pub fn builtin_if(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
     builtin_assert!("if"; args.len() >= 2);
     builtin_assert!("if"; args.len() <= 3);
-    builtin_assert!("if"; args[0] is number);
     builtin_assert!("if"; args[1] is qexpr);
 
     if args.len() == 3 {
         builtin_assert!("if"; args[2] is qexpr);
     }
 
-    let test = args.remove(0).into_num();
+    let test = args.remove(0).is_truthy();
     let consequence = args.remove(0);
     let alternative = if args.len() == 1 {
         args.remove(0)
@@ -127,43 +136,33 @@ pub fn builtin_if(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
         LVal::sexpr()
     };
 
-    let branch = if test != 0. {
+    let branch = if test {
         consequence
     } else {
         alternative
     };
 
-    eval(env, LVal::SExpr(branch.into_values()))
+    // Evaluate the branch in tail position
+    LVal::TailCall(branch.into_code())
 }
 
 
 pub fn builtin_and(_: &mut LEnv, args: Vec<LVal>) -> LVal {
     builtin_assert!("and"; args.len() == 2);
-    builtin_assert!("and"; args[*] is number);
-
-    let n1 = (*args[0].as_num()) != 0.;
-    let n2 = (*args[1].as_num()) != 0.;
 
-    LVal::Num((n1 && n2) as f64)
+    LVal::Bool(args[0].is_truthy() && args[1].is_truthy())
 }
 
 
 pub fn builtin_or(_: &mut LEnv, args: Vec<LVal>) -> LVal {
     builtin_assert!("or"; args.len() == 2);
-    builtin_assert!("or"; args[*] is number);
 
-    let n1 = (*args[0].as_num()) != 0.;
-    let n2 = (*args[1].as_num()) != 0.;
-
-    LVal::Num((n1 || n2) as f64)
+    LVal::Bool(args[0].is_truthy() || args[1].is_truthy())
 }
 
 
 pub fn builtin_not(_: &mut LEnv, args: Vec<LVal>) -> LVal {
     builtin_assert!("not"; args.len() == 1);
-    builtin_assert!("not"; args[0] is number);
-
-    let n = (*args[0].as_num()) != 0.;
 
-    LVal::Num((!n) as f64)
+    LVal::Bool(!args[0].is_truthy())
 }
\ No newline at end of file
@@ -1,13 +1,14 @@
 use std::fmt;
 use std::fs::File;
 use std::io::Read;
-use lval::LVal;
+use lval::{LVal, LError};
 use lenv::LEnv;
-use eval::eval;
+use eval::{eval, macroexpand_1, quasiquote};
 use parser::Parser;
+use resolve::resolve_lambda;
 
 
-pub fn builtin_lambda(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+pub fn builtin_lambda(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
     builtin_assert!("\\"; args.len() == 2);
     builtin_assert!("\\"; args[0] is qexpr);
     builtin_assert!("\\"; args[1] is qexpr);
@@ -15,14 +16,118 @@ pub fn builtin_lambda(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
     let formals = args.remove(0);
     let body    = args.remove(0);
 
-    for argument in formals.as_values() {
+    for argument in formals.as_list().iter() {
         if let LVal::Sym(_) = *argument {}
         else {
-            err!("cannot use non-symbol as argument: `{}`", argument)
+            err!(kind: "type-error"; "cannot use non-symbol as argument: `{}`", argument)
         }
     }
 
-    LVal::lambda(formals, body)
+    let body = resolve_lambda(env, &formals, body);
+
+    LVal::lambda(env, formals, body)
+}
+
+
+pub fn builtin_macro(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("macro"; args.len() == 2);
+    builtin_assert!("macro"; args[0] is qexpr);
+    builtin_assert!("macro"; args[1] is qexpr);
+
+    let formals = args.remove(0);
+    let body    = args.remove(0);
+
+    for argument in formals.as_list().iter() {
+        if let LVal::Sym(_) = *argument {}
+        else {
+            err!(kind: "type-error"; "cannot use non-symbol as argument: `{}`", argument)
+        }
+    }
+
+    LVal::mac(env, formals, body)
+}
+
+
+pub fn builtin_defmacro(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("defmacro"; args.len() == 2);
+    builtin_assert!("defmacro"; args[0] != {});
+    builtin_assert!("defmacro"; args[1] is qexpr);
+
+    // The first symbol is the macro's name, the rest are its arguments
+    let mut formals = args.remove(0).into_values();
+    let name = formals.remove(0);
+    let body = args.remove(0);
+
+    if let LVal::Sym(_) = name {}
+    else {
+        err!(kind: "type-error"; "cannot `defmacro` non-symbol: `{}`", name)
+    }
+
+    for argument in &formals {
+        if let LVal::Sym(_) = *argument {}
+        else {
+            err!(kind: "type-error"; "cannot use non-symbol as argument: `{}`", argument)
+        }
+    }
+
+    let mac = LVal::mac(env, LVal::qexpr_of(formals), body);
+    env.def(name, mac);
+
+    LVal::sexpr()
+}
+
+
+pub fn builtin_macroexpand_1(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("macroexpand-1"; args.len() == 1);
+    builtin_assert!("macroexpand-1"; args[0] is qexpr);
+
+    let form = args.remove(0);
+    let expansion = macroexpand_1(env, &form.values());
+
+    match expansion {
+        Some(expansion) => expansion,
+        None => form
+    }
+}
+
+
+pub fn builtin_macroexpand(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("macroexpand"; args.len() == 1);
+    builtin_assert!("macroexpand"; args[0] is qexpr);
+
+    let mut form = args.remove(0);
+
+    // Expand until the form isn't a macro call any more
+    loop {
+        let expansion = if let LVal::QExpr(ref list) = form {
+            macroexpand_1(env, &list.to_shared())
+        } else {
+            None
+        };
+
+        match expansion {
+            Some(expansion) => form = expansion,
+            None => return form
+        }
+    }
+}
+
+
+pub fn builtin_quasiquote(env: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("quasiquote"; args.len() == 1);
+    builtin_assert!("quasiquote"; args[0] is qexpr);
+
+    quasiquote(env, &args[0].values())
+}
+
+
+pub fn builtin_unquote(_: &mut LEnv, _: Vec<LVal>) -> LVal {
+    err!(kind: "syntax-error"; "`unquote` used outside of a quasiquote")
+}
+
+
+pub fn builtin_unquote_splicing(_: &mut LEnv, _: Vec<LVal>) -> LVal {
+    err!(kind: "syntax-error"; "`unquote-splicing` used outside of a quasiquote")
 }
 
 
@@ -51,13 +156,13 @@ pub fn builtin_var(loc: VariableLocation, env: &mut LEnv, mut args: Vec<LVal>) -
     for symbol in &symbols {
         if let LVal::Sym(_) = *symbol {}
         else {
-            err!("cannot `def`ine non-symbol: `{}`", symbol)
+            err!(kind: "type-error"; "cannot `def`ine non-symbol: `{}`", symbol)
        }
     }
 
     // Check that number of symbols and values matches
     if symbols.len() != args.len() {
-        err!("`def` called with number of symbols ({}) != number of values ({})",
+        err!(kind: "arity-error"; "`def` called with number of symbols ({}) != number of values ({})",
              symbols.len(), args.len())
     }
 
@@ -86,15 +191,15 @@ pub fn builtin_put(env: &mut LEnv, args: Vec<LVal>) -> LVal {
 }
 
 
-pub fn builtin_eval(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+pub fn builtin_eval(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
     builtin_assert!("eval"; args.len() == 1);
     builtin_assert!("eval"; args[0] is qexpr);
 
     // Take 1st argument
     let qexpr = args.remove(0);
 
-    // Evaluate it
-    eval(env, LVal::SExpr(qexpr.into_values()))
+    // Evaluate it in tail position
+    LVal::TailCall(qexpr.into_code())
 }
 
 
@@ -102,29 +207,40 @@ pub fn builtin_load(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
     builtin_assert!("load"; args.len() == 1);
     builtin_assert!("load"; args[0] is string);
 
-    // Read the file
     let filename = args.remove(0).into_str();
+    load_file(env, &filename, &eval)
+}
+
+
+/// Run a file with the given evaluator, printing errors as they occur
+pub fn load_file(env: &mut LEnv, filename: &str,
+                 evaluate: &Fn(&mut LEnv, LVal) -> LVal) -> LVal {
+    // Read the file
     let mut file = match File::open(&Path::new(&filename)) {
         Ok(f) => f,
-        Err(err) => return LVal::err(format!("{}", err))
+        Err(err) => return LVal::err_kind("io-error", format!("{}", err))
     };
 
     let mut contents = String::new();
     match file.read_to_string(&mut contents) {
         Ok(s) => s,
-        Err(err) => return LVal::err(format!("{}", err))
+        Err(err) => return LVal::err_kind("io-error", format!("{}", err))
     };
 
     // Parse it
-    let ast = match Parser::parse(&contents, &filename) {
+    let ast = match Parser::parse(&contents, filename) {
         Ok(lval) => lval,
-        Err(err) => return LVal::err(format!("{:?}", err))
+        Err(err) => {
+            let mut error = LError::new("parse-error", err.message());
+            error.location = Some(err.location().clone());
+            return LVal::Err(error)
+        }
     };
     let exprs = LVal::from_ast(ast).into_values();
 
     // Run it
     for val in exprs {
-        let result = eval(env, val);
+        let result = evaluate(env, val);
 
         if let LVal::Err(..) = result {
             result.println(env);
@@ -135,15 +251,6 @@ pub fn builtin_load(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
 }
 
 
-pub fn builtin_error(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
-    builtin_assert!("error"; args.len() == 1);
-    builtin_assert!("error"; args[0] is string);
-
-    let msg = args.remove(0).into_str();
-    LVal::Err(msg)
-}
-
-
 pub fn builtin_println(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
     builtin_assert!("println"; args.len() == 1);
 
@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+use lval::{LVal, LError, LKey};
+use lenv::LEnv;
+use eval::{eval, call};
+
+
+/// Get the kind of an error from a quoted symbol like `'not-found`
+fn quoted_kind(value: &LVal) -> Option<String> {
+    if let LVal::QExpr(ref list) = *value {
+        if list.len() == 1 {
+            if let Some(&LVal::Sym(kind)) = list.head() {
+                return Some(kind.to_string())
+            }
+        }
+    }
+
+    None
+}
+
+
+/// Create an error: `error [kind] message [data]`
+///
+/// The kind is given as a quoted symbol and defaults to `user-error`. A
+/// message that isn't a string is used as the payload instead. Passing a
+/// caught error raises it again.
+pub fn builtin_error(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("error"; args.len() >= 1);
+    builtin_assert!("error"; args.len() <= 3);
+
+    if args.len() == 1 && lval_is!(args[0], caught) {
+        return LVal::Err(args.remove(0).as_err().clone())
+    }
+
+    let kind = quoted_kind(&args[0]);
+    let kind = match kind {
+        Some(kind) => { args.remove(0); kind },
+        None => "user-error".to_string()
+    };
+
+    if args.len() == 0 {
+        err!(kind: "arity-error"; "`error` called without a message")
+    } else if args.len() > 2 {
+        err!(kind: "arity-error"; "`error` called with three arguments, but the first one \
+                                   isn't a quoted kind: `{}`", args[0])
+    }
+
+    let (message, data) = match args.remove(0) {
+        message @ LVal::Str(..) => (message.into_str(), args.pop()),
+        payload => {
+            if args.len() > 0 {
+                err!(kind: "type-error"; "`error` called with wrong type for the message: \
+                                          expected a string, got {}: `{}`",
+                     payload.type_name(), payload)
+            }
+
+            (format!("{}", payload), Some(payload))
+        }
+    };
+
+    let mut err = LError::new(&kind, message);
+    err.data = data.map(|data| Box::new(data));
+
+    LVal::Err(err)
+}
+
+
+/// Handle errors: `try {body} handler [{finally}]`
+///
+/// Evaluates `body`. If that fails, the handler is called with the error as
+/// a caught error and its result is returned instead. The `finally` body is
+/// evaluated in any case; its value is discarded unless it fails itself.
+///
+/// Running out of fuel or exceeding the depth limit can't be caught, as the
+/// handler would fail the same way.
+pub fn builtin_try(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("try"; args.len() >= 2);
+    builtin_assert!("try"; args.len() <= 3);
+    builtin_assert!("try"; args[0] is qexpr);
+    builtin_assert!("try"; args[1] is function);
+
+    if args.len() == 3 {
+        builtin_assert!("try"; args[2] is qexpr);
+    }
+
+    let body = args.remove(0);
+    let handler = args.remove(0);
+
+    let result = match eval(env, LVal::sexpr_of(body.into_values())) {
+        LVal::Err(err) => {
+            if err.kind == "out-of-fuel" || err.kind == "stack-overflow" {
+                LVal::Err(err)
+            } else {
+                call(env, handler, vec![LVal::Caught(err)])
+            }
+        },
+        value => value
+    };
+
+    if args.len() == 1 {
+        let cleanup = args.remove(0);
+
+        if let err @ LVal::Err(..) = eval(env, LVal::sexpr_of(cleanup.into_values())) {
+            return err
+        }
+    }
+
+    result
+}
+
+
+pub fn builtin_error_kind(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("error-kind"; args.len() == 1);
+    builtin_assert!("error-kind"; args[0] is caught);
+
+    // Return the kind quoted, so it can be compared with `'kind`
+    LVal::qexpr_of(vec![LVal::sym(&args[0].as_err().kind)])
+}
+
+
+pub fn builtin_error_message(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("error-message"; args.len() == 1);
+    builtin_assert!("error-message"; args[0] is caught);
+
+    LVal::str(&args[0].as_err().message)
+}
+
+
+pub fn builtin_error_data(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("error-data"; args.len() == 1);
+    builtin_assert!("error-data"; args[0] is caught);
+
+    match args[0].as_err().data {
+        Some(ref data) => (**data).clone(),
+        None => LVal::nil()
+    }
+}
+
+
+/// Get the calls in progress when an error occured, innermost first
+///
+/// Each call is a map with the keys `"function"` and `"location"`.
+pub fn builtin_error_backtrace(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("error-backtrace"; args.len() == 1);
+    builtin_assert!("error-backtrace"; args[0] is caught);
+
+    let backtrace = match args[0].as_err().backtrace {
+        Some(ref backtrace) => backtrace,
+        None => return LVal::qexpr()
+    };
+
+    LVal::qexpr_of(backtrace.iter().map(|frame| {
+        let location = match frame.location {
+            Some(ref location) => LVal::str(&format!("{}", location)),
+            None => LVal::nil()
+        };
+
+        let mut call = BTreeMap::new();
+        call.insert(LKey::Str("function".to_string()), LVal::str(&frame.name));
+        call.insert(LKey::Str("location".to_string()), location);
+
+        LVal::map(call)
+    }).collect())
+}
+
+
+// --- Tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use lval::LVal;
+    use lenv::LEnv;
+    use testing::run;
+    use fuel;
+    use stack;
+    use super::*;
+
+    fn error(args: Vec<LVal>) -> LVal {
+        builtin_error(&mut LEnv::new(), args)
+    }
+
+    /// Create an error like `error` and catch it
+    fn caught(args: Vec<LVal>) -> LVal {
+        match error(args) {
+            LVal::Err(err) => LVal::Caught(err),
+            other => panic!("not an error: {}", other)
+        }
+    }
+
+    #[test]
+    fn builtin_error_default_kind() {
+        assert_eq!(error(vec![LVal::str("boom")]),
+                   LVal::err_kind("user-error", "boom".to_string()));
+
+        let err = caught(vec![LVal::str("boom")]);
+        assert_eq!(
+            builtin_error_kind(&mut LEnv::new(), vec![err.clone()]),
+            LVal::qexpr_of(vec![LVal::sym("user-error")])
+        );
+        assert_eq!(builtin_error_data(&mut LEnv::new(), vec![err]), LVal::nil())
+    }
+
+    #[test]
+    fn builtin_error_kind_and_data() {
+        let err = caught(vec![
+            LVal::qexpr_of(vec![LVal::sym("not-found")]),
+            LVal::str("no such user"),
+            LVal::int(42)
+        ]);
+
+        assert_eq!(
+            builtin_error_kind(&mut LEnv::new(), vec![err.clone()]),
+            LVal::qexpr_of(vec![LVal::sym("not-found")])
+        );
+        assert_eq!(
+            builtin_error_message(&mut LEnv::new(), vec![err.clone()]),
+            LVal::str("no such user")
+        );
+        assert_eq!(builtin_error_data(&mut LEnv::new(), vec![err]), LVal::int(42))
+    }
+
+    #[test]
+    fn builtin_error_payload() {
+        let payload = LVal::qexpr_of(vec![LVal::int(1), LVal::int(2)]);
+        let err = caught(vec![payload.clone()]);
+
+        assert_eq!(
+            builtin_error_message(&mut LEnv::new(), vec![err.clone()]),
+            LVal::str("{1 2}")
+        );
+        assert_eq!(builtin_error_data(&mut LEnv::new(), vec![err]), payload)
+    }
+
+    #[test]
+    fn builtin_error_raises_caught() {
+        let err = caught(vec![LVal::str("boom")]);
+
+        assert_eq!(error(vec![err]), LVal::err_kind("user-error", "boom".to_string()))
+    }
+
+    #[test]
+    fn builtin_error_inspection_from_code() {
+        assert_eq!(
+            run(&["(try {error {not-found} \"no such user\" 42} \
+                        (\\ {e} {list (error-kind e) (error-message e) (error-data e)}))"]),
+            LVal::qexpr_of(vec![
+                LVal::qexpr_of(vec![LVal::sym("not-found")]),
+                LVal::str("no such user"),
+                LVal::int(42)
+            ])
+        );
+        assert_eq!(
+            run(&["(try {error \"boom\"} (\\ {e} {error-backtrace e}))"]),
+            LVal::qexpr()
+        )
+    }
+
+    #[test]
+    fn builtin_try_limits_uncaught() {
+        stack::set_max_depth(200);
+        let result = run(&[
+            "(def {down} (\\ {n} {+ 1 (down (- n 1))}))",
+            "(try {down 10} (\\ {e} {0}))"
+        ]);
+        stack::set_max_depth(stack::DEFAULT_MAX_DEPTH);
+
+        assert_eq!(result.as_err().kind, "stack-overflow");
+
+        fuel::set_budget(Some(1000));
+        let result = run(&[
+            "(def {forever} (\\ {n} {forever (+ n 1)}))",
+            "(try {forever 0} (\\ {e} {0}))"
+        ]);
+        fuel::set_budget(None);
+
+        assert_eq!(result, fuel::out_of_fuel())
+    }
+}
@@ -0,0 +1,305 @@
+use std::iter::repeat;
+use lval::LVal;
+use lenv::LEnv;
+
+
+/// The largest width and precision a placeholder may have
+const MAX_WIDTH: usize = 1024;
+
+
+/// A `{...}` placeholder
+///
+/// The syntax is `{[index][:[<|>][0][width][.precision][?]]}`:
+///
+/// - `index` selects an argument, by default the next one is used
+/// - `<` and `>` align left or right, numbers are aligned right by default
+/// - `0` pads numbers with zeros instead of spaces
+/// - `width` is the minimal number of characters
+/// - `precision` is the number of decimals of a number or the maximum
+///   number of characters of a string
+/// - `?` shows the value like the REPL does, e.g. strings in quotes
+struct Placeholder {
+    index: Option<usize>,
+    align: Option<char>,
+    zero: bool,
+    width: Option<usize>,
+    precision: Option<usize>,
+    repr: bool
+}
+
+impl Placeholder {
+    fn parse(spec: &str) -> Result<Placeholder, String> {
+        let mut placeholder = Placeholder {
+            index: None,
+            align: None,
+            zero: false,
+            width: None,
+            precision: None,
+            repr: false
+        };
+
+        let (index, options) = match spec.find(':') {
+            Some(colon) => (&spec[..colon], &spec[colon + 1..]),
+            None => (spec, "")
+        };
+
+        if !index.is_empty() {
+            placeholder.index = match index.parse() {
+                Ok(index) => Some(index),
+                Err(..) => return Err(format!("invalid argument index: `{}`", index))
+            };
+        }
+
+        let mut chars = options.chars().peekable();
+
+        if let Some(&c) = chars.peek() {
+            if c == '<' || c == '>' {
+                placeholder.align = Some(c);
+                chars.next();
+            }
+        }
+
+        if chars.peek() == Some(&'0') {
+            placeholder.zero = true;
+            chars.next();
+        }
+
+        let mut width = String::new();
+        while let Some(&c) = chars.peek() {
+            if !c.is_digit(10) { break }
+            width.push(c);
+            chars.next();
+        }
+
+        if !width.is_empty() {
+            match width.parse() {
+                Ok(width) if width <= MAX_WIDTH => placeholder.width = Some(width),
+                _ => return Err(format!("width too large in `{{{}}}`, the maximum is {}",
+                                        spec, MAX_WIDTH))
+            }
+        }
+
+        if chars.peek() == Some(&'.') {
+            chars.next();
+
+            let mut precision = String::new();
+            while let Some(&c) = chars.peek() {
+                if !c.is_digit(10) { break }
+                precision.push(c);
+                chars.next();
+            }
+
+            if precision.is_empty() {
+                return Err(format!("missing precision in `{{{}}}`", spec))
+            }
+
+            match precision.parse() {
+                Ok(precision) if precision <= MAX_WIDTH => placeholder.precision = Some(precision),
+                _ => return Err(format!("precision too large in `{{{}}}`, the maximum is {}",
+                                        spec, MAX_WIDTH))
+            }
+        }
+
+        if chars.peek() == Some(&'?') {
+            placeholder.repr = true;
+            chars.next();
+        }
+
+        if chars.next().is_some() {
+            return Err(format!("invalid placeholder: `{{{}}}`", spec))
+        }
+
+        Ok(placeholder)
+    }
+
+    /// Render a value as described by the placeholder
+    fn render(&self, value: &LVal) -> String {
+        let is_number = lval_is!(*value, number);
+
+        let text = match (value, self.precision) {
+            (_, Some(precision)) if is_number => format!("{:.*}", precision, value.as_num()),
+            (&LVal::Str(ref s), Some(precision)) if !self.repr => {
+                s.chars().take(precision).collect()
+            },
+            (&LVal::Str(ref s), None) if !self.repr => (**s).clone(),
+            _ => format!("{}", value)
+        };
+
+        let length = text.chars().count();
+        let width = match self.width {
+            Some(width) if width > length => width,
+            _ => return text
+        };
+        let padding = width - length;
+
+        if self.zero && is_number {
+            // Put the zeros between the sign and the digits
+            let (sign, digits) = if text.starts_with("-") {
+                ("-", &text[1..])
+            } else {
+                ("", &text[..])
+            };
+
+            return format!("{}{}{}", sign, repeat('0').take(padding).collect::<String>(), digits)
+        }
+
+        let fill = repeat(' ').take(padding).collect::<String>();
+        let align = match self.align {
+            Some(align) => align,
+            None => if is_number { '>' } else { '<' }
+        };
+
+        if align == '>' {
+            format!("{}{}", fill, text)
+        } else {
+            format!("{}{}", text, fill)
+        }
+    }
+}
+
+
+/// Fill the placeholders in `fmt` with `args`
+///
+/// `{{` and `}}` stand for literal braces. All arguments have to be used.
+pub fn format(fmt: &str, args: &[LVal]) -> Result<String, LVal> {
+    let mut result = String::new();
+    let mut used = repeat(false).take(args.len()).collect::<Vec<_>>();
+    let mut next = 0;
+    let mut chars = fmt.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => { chars.next(); result.push('{'); },
+            '}' if chars.peek() == Some(&'}') => { chars.next(); result.push('}'); },
+            '{' => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => spec.push(c),
+                        None => return Err(LVal::err_kind("format-error", format!(
+                            "unterminated placeholder in `{}`", fmt)))
+                    }
+                }
+
+                let placeholder = match Placeholder::parse(&spec) {
+                    Ok(placeholder) => placeholder,
+                    Err(msg) => return Err(LVal::err_kind("format-error", msg))
+                };
+
+                let index = match placeholder.index {
+                    Some(index) => index,
+                    None => { next += 1; next - 1 }
+                };
+
+                if index >= args.len() {
+                    return Err(LVal::err_kind("arity-error", format!(
+                        "`{}` needs at least {} arguments, got {}", fmt, index + 1, args.len())))
+                }
+
+                used[index] = true;
+                result.push_str(&placeholder.render(&args[index]));
+            },
+            '}' => return Err(LVal::err_kind("format-error", format!(
+                "unmatched `}}` in `{}`", fmt))),
+            c => result.push(c)
+        }
+    }
+
+    if let Some(unused) = used.iter().position(|used| !*used) {
+        return Err(LVal::err_kind("arity-error", format!(
+            "argument {} is not used by `{}`: `{}`", unused + 1, fmt, args[unused])))
+    }
+
+    Ok(result)
+}
+
+
+/// Build a string from a format string and values: `format fmt values...`
+pub fn builtin_format(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("format"; args.len() >= 1);
+    builtin_assert!("format"; args[0] is string);
+
+    let fmt = args.remove(0).into_str();
+
+    match format(&fmt, &args) {
+        Ok(string) => LVal::string(string),
+        Err(err) => err
+    }
+}
+
+
+/// Print a formatted string without a newline: `printf fmt values...`
+pub fn builtin_printf(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("printf"; args.len() >= 1);
+    builtin_assert!("printf"; args[0] is string);
+
+    let fmt = args.remove(0).into_str();
+
+    match format(&fmt, &args) {
+        Ok(string) => {
+            print!("{}", string);
+            LVal::sexpr()
+        },
+        Err(err) => err
+    }
+}
+
+
+// --- Tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use lval::LVal;
+    use super::format;
+
+    #[test]
+    fn format_display_and_repr() {
+        assert_eq!(
+            format("{} = {:?}", &[LVal::str("x"), LVal::str("x")]),
+            Ok("x = \"x\"".to_string())
+        );
+        assert_eq!(
+            format("{1} {0} {{}}", &[LVal::int(1), LVal::int(2)]),
+            Ok("2 1 {}".to_string())
+        )
+    }
+
+    #[test]
+    fn format_width_precision() {
+        assert_eq!(
+            format("[{:6.2}] [{:<4}] [{:03}] [{:.3}]", &[
+                LVal::num(3.14159), LVal::int(7), LVal::int(-5), LVal::str("abcdef")
+            ]),
+            Ok("[  3.14] [7   ] [-05] [abc]".to_string())
+        )
+    }
+
+    #[test]
+    fn format_errors() {
+        assert_eq!(
+            format("{} {}", &[LVal::int(1)]),
+            Err(LVal::err_kind("arity-error",
+                               "`{} {}` needs at least 2 arguments, got 1".to_string()))
+        );
+        assert_eq!(
+            format("{}", &[LVal::int(1), LVal::int(2)]),
+            Err(LVal::err_kind("arity-error",
+                               "argument 2 is not used by `{}`: `2`".to_string()))
+        )
+    }
+
+    #[test]
+    fn format_huge_width() {
+        assert_eq!(
+            format("{:99999999999999999999}", &[LVal::int(1)]),
+            Err(LVal::err_kind("format-error", "width too large in \
+                               `{:99999999999999999999}`, the maximum is 1024".to_string()))
+        );
+        assert_eq!(
+            format("{:.2000}", &[LVal::num(1.0)]),
+            Err(LVal::err_kind("format-error",
+                               "precision too large in `{:.2000}`, the maximum is 1024".to_string()))
+        )
+    }
+}
@@ -1,20 +1,20 @@
+use std::fmt;
+use std::cmp::Ordering;
 use lval::LVal;
+use list::List;
 use lenv::LEnv;
+use eval::call;
+use builtin::math::compare;
 
 
 pub fn builtin_head(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
     builtin_assert!("head"; args.len() == 1);
     builtin_assert!("head"; args[0] != {});
 
-    // Take 1st argument
-    let mut qexpr = args.remove(0).into_values();
-
     // Take 1st element and return it
-    for _ in 0 .. qexpr.len() - 1 {
-        qexpr.remove(1);
-    }
+    let first = args[0].as_list().head().unwrap().clone();
 
-    LVal::QExpr(qexpr)
+    LVal::QExpr(List::new().cons(first))
 }
 
 
@@ -22,30 +22,32 @@ pub fn builtin_tail(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
     builtin_assert!("tail"; args.len() == 1);
     builtin_assert!("tail"; args[0] != {});
 
-    // Take 1st argument
-    let qexpr = args.remove(0);
-
-    // Remove 1st element and return the tail
-    LVal::QExpr(qexpr.into_values().into_iter().skip(1).collect())
-
+    // Return everything but the 1st element, sharing it with the argument
+    LVal::QExpr(args[0].as_list().tail().unwrap())
 }
 
 
 pub fn builtin_list(_: &mut LEnv, args: Vec<LVal>) -> LVal {
-    LVal::QExpr(args)
+    LVal::qexpr_of(args)
 }
 
 
 pub fn builtin_join(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
     builtin_assert!("join"; args[*] is qexpr);
 
-    let mut joined = args.remove(0);
+    // The last list is shared, the elements of the others are consed onto it
+    let mut joined = match args.pop() {
+        Some(last) => last.as_list().clone(),
+        None => List::new()
+    };
 
-    for arg in args {
-        joined.extend(arg);
+    for arg in args.iter().rev() {
+        for value in arg.as_list().to_vec().into_iter().rev() {
+            joined = joined.cons(value);
+        }
     }
 
-    joined
+    LVal::QExpr(joined)
 }
 
 
@@ -53,9 +55,255 @@ pub fn builtin_cons(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
     builtin_assert!("cons"; args.len() == 2);
     builtin_assert!("cons"; args[1] is qexpr);
 
-    let mut value = LVal::qexpr();
-    value.append(args.remove(0));
-    value.extend(LVal::QExpr(args));
+    let value = args.remove(0);
+
+    LVal::QExpr(args[0].as_list().cons(value))
+}
+
+
+pub fn builtin_map(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("map"; args.len() == 2);
+    builtin_assert!("map"; args[0] is function);
+    builtin_assert!("map"; args[1] is qexpr);
+
+    let func = args.remove(0);
+    let mut mapped = vec![];
+
+    for value in args.remove(0).into_values() {
+        match call(env, func.clone(), vec![value]) {
+            err @ LVal::Err(..) => return err,
+            value => mapped.push(value)
+        }
+    }
+
+    LVal::qexpr_of(mapped)
+}
+
+
+pub fn builtin_filter(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("filter"; args.len() == 2);
+    builtin_assert!("filter"; args[0] is function);
+    builtin_assert!("filter"; args[1] is qexpr);
+
+    let func = args.remove(0);
+    let mut filtered = vec![];
+
+    for value in args.remove(0).into_values() {
+        match call(env, func.clone(), vec![value.clone()]) {
+            err @ LVal::Err(..) => return err,
+            keep => if keep.is_truthy() { filtered.push(value) }
+        }
+    }
+
+    LVal::qexpr_of(filtered)
+}
+
+
+enum FoldDirection {
+    Left,
+    Right
+}
+
+impl fmt::Display for FoldDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FoldDirection::Left  => write!(f, "{}", "foldl"),
+            FoldDirection::Right => write!(f, "{}", "foldr")
+        }
+    }
+}
+
+
+/// Combine the elements of a list: `foldl f init {a b}` is `(f (f init a) b)`
+/// and `foldr f init {a b}` is `(f a (f b init))`
+pub fn builtin_fold(direction: FoldDirection, env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!(direction; args.len() == 3);
+    builtin_assert!(direction; args[0] is function);
+    builtin_assert!(direction; args[2] is qexpr);
+
+    let func = args.remove(0);
+    let mut acc = args.remove(0);
+    let values = args.remove(0).into_values();
+
+    match direction {
+        FoldDirection::Left => {
+            for value in values {
+                acc = call(env, func.clone(), vec![acc, value]);
+                if let LVal::Err(..) = acc { return acc }
+            }
+        },
+        FoldDirection::Right => {
+            for value in values.into_iter().rev() {
+                acc = call(env, func.clone(), vec![value, acc]);
+                if let LVal::Err(..) = acc { return acc }
+            }
+        }
+    }
+
+    acc
+}
+
+
+pub fn builtin_foldl(env: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_fold(FoldDirection::Left, env, args)
+}
+
+
+pub fn builtin_foldr(env: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_fold(FoldDirection::Right, env, args)
+}
+
+
+/// Like `foldl`, but starts with the first element of the list
+pub fn builtin_reduce(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("reduce"; args.len() == 2);
+    builtin_assert!("reduce"; args[0] is function);
+    builtin_assert!("reduce"; args[1] != {});
+
+    let func = args.remove(0);
+    let mut values = args.remove(0).into_values();
+    let init = values.remove(0);
+
+    builtin_foldl(env, vec![func, init, LVal::qexpr_of(values)])
+}
+
+
+/// Sort a list: `sort {list} [less]`
+///
+/// Without a comparator, lists of numbers or of strings are sorted in
+/// ascending order. The comparator is called as `(less a b)` and should
+/// return whether `a` comes before `b`.
+pub fn builtin_sort(env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("sort"; args.len() >= 1);
+    builtin_assert!("sort"; args.len() <= 2);
+    builtin_assert!("sort"; args[0] is qexpr);
+
+    if args.len() == 2 {
+        builtin_assert!("sort"; args[1] is function);
+    }
+
+    let mut values = args.remove(0).into_values();
+
+    // `sort_by` can't be interrupted, so remember the first error and
+    // return it once sorting is done
+    let mut error = None;
+
+    if args.len() == 1 {
+        let less = args.remove(0);
+
+        values.sort_by(|a, b| {
+            if error.is_some() {
+                return Ordering::Equal
+            }
+
+            let mut is_less = |x: &LVal, y: &LVal| {
+                match call(env, less.clone(), vec![x.clone(), y.clone()]) {
+                    err @ LVal::Err(..) => { error = Some(err); false },
+                    result => result.is_truthy()
+                }
+            };
+
+            if is_less(a, b) {
+                Ordering::Less
+            } else if is_less(b, a) {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        });
+    } else {
+        values.sort_by(|a, b| {
+            let ordering = match (a, b) {
+                (&LVal::Str(ref x), &LVal::Str(ref y)) => Some(x.cmp(y)),
+
+                // `compare` only takes numbers
+                _ if lval_is!(*a, number) && lval_is!(*b, number) => compare(a, b),
+                _ => None
+            };
+
+            match ordering {
+                Some(ordering) => ordering,
+                None => {
+                    if error.is_none() {
+                        error = Some(LVal::err_kind("type-error", format!(
+                            "`sort` can't compare {} and {}: `{}`, `{}`",
+                            a.type_name(), b.type_name(), a, b)));
+                    }
+
+                    Ordering::Equal
+                }
+            }
+        });
+    }
+
+    match error {
+        Some(err) => err,
+        None => LVal::qexpr_of(values)
+    }
+}
+
+
+enum Quantifier {
+    Any,
+    All
+}
+
+impl fmt::Display for Quantifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Quantifier::Any => write!(f, "{}", "any"),
+            Quantifier::All => write!(f, "{}", "all")
+        }
+    }
+}
+
+
+/// Check whether a predicate holds for any/all elements of a list
+///
+/// Stops as soon as the result is known.
+pub fn builtin_quantifier(quantifier: Quantifier, env: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!(quantifier; args.len() == 2);
+    builtin_assert!(quantifier; args[0] is function);
+    builtin_assert!(quantifier; args[1] is qexpr);
+
+    let func = args.remove(0);
+    let wanted = match quantifier {
+        Quantifier::Any => true,
+        Quantifier::All => false
+    };
+
+    for value in args.remove(0).into_values() {
+        match call(env, func.clone(), vec![value]) {
+            err @ LVal::Err(..) => return err,
+            result => if result.is_truthy() == wanted { return LVal::Bool(wanted) }
+        }
+    }
+
+    LVal::Bool(!wanted)
+}
+
+
+pub fn builtin_any(env: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_quantifier(Quantifier::Any, env, args)
+}
+
+
+pub fn builtin_all(env: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_quantifier(Quantifier::All, env, args)
+}
+
+
+/// Pair up the elements of lists: `zip {a b} {1 2}` is `{{a 1} {b 2}}`
+///
+/// The result is as long as the shortest list.
+pub fn builtin_zip(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("zip"; args.len() >= 1);
+    builtin_assert!("zip"; args[*] is qexpr);
+
+    let length = args.iter().map(|list| list.as_list().len()).min().unwrap();
+    let mut lists: Vec<_> = args.iter().map(|list| list.as_list().iter()).collect();
 
-    value
+    LVal::qexpr_of((0 .. length).map(|_| {
+        LVal::qexpr_of(lists.iter_mut().map(|list| list.next().unwrap().clone()).collect())
+    }).collect())
 }
\ No newline at end of file
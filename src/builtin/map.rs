@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use lval::{LVal, LKey};
+use lenv::LEnv;
+
+
+/// Convert a value to a map key or return an error
+fn to_key(func: &str, value: &LVal) -> Result<LKey, LVal> {
+    match LKey::from_lval(value) {
+        Some(key) => Ok(key),
+        None => Err(LVal::err_kind("type-error", format!(
+            "`{}` cannot use {} as a map key: `{}`", func, value.type_name(), value)))
+    }
+}
+
+
+pub fn builtin_hash_map(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    if args.len() % 2 != 0 {
+        err!(kind: "arity-error"; "`hash-map` called with an odd number of arguments: {}", args.len())
+    }
+
+    let mut map = BTreeMap::new();
+    let mut args = args.into_iter();
+
+    while let (Some(key), Some(value)) = (args.next(), args.next()) {
+        match to_key("hash-map", &key) {
+            Ok(key) => { map.insert(key, value); },
+            Err(err) => return err
+        }
+    }
+
+    LVal::map(map)
+}
+
+
+pub fn builtin_get(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("get"; args.len() >= 2);
+    builtin_assert!("get"; args.len() <= 3);
+    builtin_assert!("get"; args[0] is map);
+
+    let key = match to_key("get", &args[1]) {
+        Ok(key) => key,
+        Err(err) => return err
+    };
+
+    // Return the default value or nil if the key is missing
+    let default = if args.len() == 3 { args.remove(2) } else { LVal::nil() };
+
+    match args[0].as_map().get(&key) {
+        Some(value) => value.clone(),
+        None => default
+    }
+}
+
+
+pub fn builtin_assoc(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("assoc"; args.len() >= 1);
+    builtin_assert!("assoc"; args[0] is map);
+
+    let mut map = args.remove(0).into_map();
+
+    if args.len() % 2 != 0 {
+        err!(kind: "arity-error"; "`assoc` called without a value for key `{}`", args[args.len() - 1])
+    }
+
+    let mut args = args.into_iter();
+
+    while let (Some(key), Some(value)) = (args.next(), args.next()) {
+        match to_key("assoc", &key) {
+            Ok(key) => { map.insert(key, value); },
+            Err(err) => return err
+        }
+    }
+
+    LVal::map(map)
+}
+
+
+pub fn builtin_dissoc(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("dissoc"; args.len() >= 1);
+    builtin_assert!("dissoc"; args[0] is map);
+
+    let mut map = args.remove(0).into_map();
+
+    for key in &args {
+        match to_key("dissoc", key) {
+            Ok(key) => { map.remove(&key); },
+            Err(err) => return err
+        }
+    }
+
+    LVal::map(map)
+}
+
+
+pub fn builtin_keys(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("keys"; args.len() == 1);
+    builtin_assert!("keys"; args[0] is map);
+
+    LVal::qexpr_of(args[0].as_map().keys().map(|key| key.to_lval()).collect())
+}
+
+
+pub fn builtin_vals(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("vals"; args.len() == 1);
+    builtin_assert!("vals"; args[0] is map);
+
+    LVal::qexpr_of(args[0].as_map().values().cloned().collect())
+}
+
+
+pub fn builtin_contains(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("contains?"; args.len() == 2);
+    builtin_assert!("contains?"; args[0] is map);
+
+    match to_key("contains?", &args[1]) {
+        Ok(key) => LVal::Bool(args[0].as_map().contains_key(&key)),
+        Err(err) => err
+    }
+}
+
+
+pub fn builtin_merge(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("merge"; args.len() >= 1);
+    builtin_assert!("merge"; args[*] is map);
+
+    // Values from later maps win
+    let mut merged = BTreeMap::new();
+
+    for map in args {
+        merged.extend(map.into_map().into_iter());
+    }
+
+    LVal::map(merged)
+}
+
+
+// --- Tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use lval::LVal;
+    use lenv::LEnv;
+    use super::*;
+
+    fn map(values: Vec<LVal>) -> LVal {
+        builtin_hash_map(&mut LEnv::new(), values)
+    }
+
+    #[test]
+    fn builtin_get_value() {
+        assert_eq!(
+            builtin_get(&mut LEnv::new(), vec![
+                map(vec![LVal::str("a"), LVal::int(1)]),
+                LVal::str("a")
+            ]),
+            LVal::int(1)
+        )
+    }
+
+    #[test]
+    fn builtin_get_default() {
+        assert_eq!(
+            builtin_get(&mut LEnv::new(), vec![
+                map(vec![LVal::str("a"), LVal::int(1)]),
+                LVal::str("b"),
+                LVal::int(0)
+            ]),
+            LVal::int(0)
+        )
+    }
+
+    #[test]
+    fn builtin_assoc_dissoc() {
+        let m = builtin_assoc(&mut LEnv::new(), vec![
+            map(vec![LVal::str("a"), LVal::int(1)]),
+            LVal::str("b"),
+            LVal::int(2)
+        ]);
+
+        assert_eq!(m, map(vec![LVal::str("a"), LVal::int(1), LVal::str("b"), LVal::int(2)]));
+        assert_eq!(
+            builtin_dissoc(&mut LEnv::new(), vec![m, LVal::str("b")]),
+            map(vec![LVal::str("a"), LVal::int(1)])
+        )
+    }
+
+    #[test]
+    fn builtin_merge_maps() {
+        assert_eq!(
+            builtin_merge(&mut LEnv::new(), vec![
+                map(vec![LVal::str("a"), LVal::int(1), LVal::str("b"), LVal::int(2)]),
+                map(vec![LVal::str("b"), LVal::int(3)])
+            ]),
+            map(vec![LVal::str("a"), LVal::int(1), LVal::str("b"), LVal::int(3)])
+        )
+    }
+
+    #[test]
+    fn builtin_invalid_key() {
+        assert_eq!(
+            map(vec![LVal::num(1.5), LVal::int(1)]),
+            LVal::err_kind("type-error",
+                           "`hash-map` cannot use a number as a map key: `1.5`".to_string())
+        )
+    }
+}
@@ -1,4 +1,6 @@
 use std::fmt;
+use std::cmp::Ordering;
+use num::{BigRational, Integer, Zero};
 use lval::LVal;
 use lenv::LEnv;
 
@@ -33,37 +35,166 @@ fn builtin_op(op: ArithmeticOp, mut args: Vec<LVal>) -> LVal {
     builtin_assert!(op; args[*] is number);
     builtin_assert!(op; args.len() >= 1);
 
-    //let mut x = *args[0].as_num();
-    let mut x = args.remove(0).into_num();
+    let mut x = args.remove(0);
 
     // Perform unary minus operation
     if op == SUB && args.len() == 0 {
-        return LVal::num(-1.0 * x)
+        return builtin_op(op, vec![LVal::int(0), x])
     }
 
     builtin_assert!(op; args.len() >= 1);
 
-    for arg in args {
-        let y = arg.into_num();
-
-        x = match op {
-            ADD => x + y,
-            SUB => x - y,
-            MUL => x * y,
-            DIV => {
-                if y == 0.0 { err!("division by zero!") }
-                x / y
-            },
-            MOD => {
-                if y == 0.0 { err!("division by zero!") }
-                x % y
+    // Numbers are promoted along int -> big int -> rational -> float, but
+    // exact results are always stored in the smallest type they fit into
+    for y in args {
+        x = match (x, y) {
+            (LVal::Int(a), LVal::Int(b)) => match int_op(&op, a, b) {
+                Some(result) => result,
+                None => exact_op(&op, LVal::int(a).as_ratio(), LVal::int(b).as_ratio())
             },
-            MIN => if x > y { y } else { x },
-            MAX => if x > y { x } else { y }
+            (x, y) => if x.is_exact() && y.is_exact() {
+                exact_op(&op, x.as_ratio(), y.as_ratio())
+            } else {
+                float_op(&op, x.into_num(), y.into_num())
+            }
         };
+
+        if let LVal::Err(..) = x {
+            return x
+        }
+    }
+
+    x
+}
+
+
+/// Calculate with `i64`s
+///
+/// Returns `None` if the result doesn't fit into an `i64`.
+fn int_op(op: &ArithmeticOp, x: i64, y: i64) -> Option<LVal> {
+    use self::ArithmeticOp::*;
+
+    let result = match *op {
+        ADD => x.checked_add(y),
+        SUB => x.checked_sub(y),
+        MUL => x.checked_mul(y),
+        DIV => {
+            // Only exact divisions result in an integer
+            match x.checked_rem(y) {
+                Some(0) => x.checked_div(y),
+                _ => None
+            }
+        },
+        MOD => {
+            // The result has the sign of the divisor
+            x.checked_rem(y).map(|r| if r != 0 && (r < 0) != (y < 0) { r + y } else { r })
+        },
+        MIN => Some(if x > y { y } else { x }),
+        MAX => Some(if x > y { x } else { y })
+    };
+
+    result.map(LVal::int)
+}
+
+
+fn exact_op(op: &ArithmeticOp, x: BigRational, y: BigRational) -> LVal {
+    use self::ArithmeticOp::*;
+
+    LVal::ratio(match *op {
+        ADD => x + y,
+        SUB => x - y,
+        MUL => x * y,
+        DIV => {
+            if y.is_zero() { err!(kind: "division-by-zero"; "division by zero!") }
+            x / y
+        },
+        MOD => {
+            if y.is_zero() { err!(kind: "division-by-zero"; "division by zero!") }
+
+            // The result has the sign of the divisor
+            let quotient = (x.clone() / y.clone()).floor();
+            x - y * quotient
+        },
+        MIN => if x > y { y } else { x },
+        MAX => if x > y { x } else { y }
+    })
+}
+
+
+fn float_op(op: &ArithmeticOp, x: f64, y: f64) -> LVal {
+    use self::ArithmeticOp::*;
+
+    LVal::num(match *op {
+        ADD => x + y,
+        SUB => x - y,
+        MUL => x * y,
+        DIV => {
+            if y == 0.0 { err!(kind: "division-by-zero"; "division by zero!") }
+            x / y
+        },
+        MOD => {
+            if y == 0.0 { err!(kind: "division-by-zero"; "division by zero!") }
+
+            // The result has the sign of the divisor
+            x - y * (x / y).floor()
+        },
+        MIN => if x > y { y } else { x },
+        MAX => if x > y { x } else { y }
+    })
+}
+
+
+/// Compare two numbers
+///
+/// Exact numbers are compared exactly, everything else as floats.
+pub fn compare(x: &LVal, y: &LVal) -> Option<Ordering> {
+    match (x, y) {
+        (&LVal::Int(a), &LVal::Int(b)) => a.partial_cmp(&b),
+        (x, y) if x.is_exact() && y.is_exact() => x.as_ratio().partial_cmp(&y.as_ratio()),
+        (x, y) => x.as_num().partial_cmp(&y.as_num())
+    }
+}
+
+
+#[derive(PartialEq)]
+enum IntegerOp {
+    DIV, QUOT, REM
+}
+
+impl fmt::Display for IntegerOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::IntegerOp::*;
+
+        match *self {
+            DIV  => write!(f, "{}", "div"),
+            QUOT => write!(f, "{}", "quot"),
+            REM  => write!(f, "{}", "rem")
+        }
     }
+}
+
+
+fn builtin_int_op(op: IntegerOp, args: Vec<LVal>) -> LVal {
+    use self::IntegerOp::*;
+
+    builtin_assert!(op; args.len() == 2);
+    builtin_assert!(op; args[*] is integer);
+
+    let x = args[0].as_bigint();
+    let y = args[1].as_bigint();
+
+    if y.is_zero() { err!(kind: "division-by-zero"; "division by zero!") }
+
+    LVal::bigint(match op {
+        // Round towards negative infinity
+        DIV  => x.div_floor(&y),
+
+        // Round towards zero
+        QUOT => x / y,
 
-    LVal::num(x)
+        // The result has the sign of the dividend
+        REM  => x % y
+    })
 }
 
 
@@ -102,12 +233,37 @@ pub fn builtin_max(_: &mut LEnv, args: Vec<LVal>) -> LVal {
 }
 
 
+pub fn builtin_div_int(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_int_op(IntegerOp::DIV, args)
+}
+
+
+pub fn builtin_quot(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_int_op(IntegerOp::QUOT, args)
+}
+
+
+pub fn builtin_rem(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_int_op(IntegerOp::REM, args)
+}
+
+
 // --- Tests --------------------------------------------------------------------
 
 #[cfg(test)]
 mod test {
+    use num::{BigInt, BigRational, FromPrimitive};
     use lval::LVal;
-    use super::{builtin_op, ArithmeticOp};
+    use super::{builtin_op, ArithmeticOp, builtin_int_op, IntegerOp};
+
+    fn bigint(s: &str) -> LVal {
+        LVal::bigint(s.parse().unwrap())
+    }
+
+    fn ratio(numer: i64, denom: i64) -> LVal {
+        LVal::ratio(BigRational::new(BigInt::from_i64(numer).unwrap(),
+                                     BigInt::from_i64(denom).unwrap()))
+    }
 
     #[test]
     fn builtin_op_few_arguments() {
@@ -115,7 +271,8 @@ mod test {
             builtin_op(ArithmeticOp::ADD, vec![
                 LVal::num(2.0)
             ]),
-            LVal::err("`+` called with too few arguments: expected at least 1, got 0".to_string())
+            LVal::err_kind("arity-error",
+                           "`+` called with too few arguments: expected at least 1, got 0".to_string())
         )
     }
 
@@ -207,4 +364,145 @@ mod test {
             LVal::num(3.0)
         )
     }
+
+    #[test]
+    fn builtin_op_int() {
+        assert_eq!(
+            builtin_op(ArithmeticOp::MUL, vec![
+                LVal::int(2),
+                LVal::int(3)
+            ]),
+            LVal::int(6)
+        )
+    }
+
+    #[test]
+    fn builtin_op_mixed() {
+        assert_eq!(
+            builtin_op(ArithmeticOp::ADD, vec![
+                LVal::int(2),
+                LVal::num(0.5)
+            ]),
+            LVal::num(2.5)
+        )
+    }
+
+    #[test]
+    fn builtin_op_div_int() {
+        assert_eq!(
+            builtin_op(ArithmeticOp::DIV, vec![
+                LVal::int(6),
+                LVal::int(3)
+            ]),
+            LVal::int(2)
+        );
+        assert_eq!(
+            builtin_op(ArithmeticOp::DIV, vec![
+                LVal::int(7),
+                LVal::int(2)
+            ]),
+            ratio(7, 2)
+        )
+    }
+
+    #[test]
+    fn builtin_op_modulo_negative() {
+        assert_eq!(
+            builtin_op(ArithmeticOp::MOD, vec![
+                LVal::int(-7),
+                LVal::int(3)
+            ]),
+            LVal::int(2)
+        );
+        assert_eq!(
+            builtin_op(ArithmeticOp::MOD, vec![
+                LVal::num(-7.0),
+                LVal::num(3.0)
+            ]),
+            LVal::num(2.0)
+        )
+    }
+
+    #[test]
+    fn builtin_op_promote() {
+        assert_eq!(
+            builtin_op(ArithmeticOp::ADD, vec![
+                LVal::int(9223372036854775807),
+                LVal::int(1)
+            ]),
+            bigint("9223372036854775808")
+        );
+        assert_eq!(
+            builtin_op(ArithmeticOp::MUL, vec![
+                bigint("123456789012345678901234567890"),
+                LVal::int(10)
+            ]),
+            bigint("1234567890123456789012345678900")
+        )
+    }
+
+    #[test]
+    fn builtin_op_demote() {
+        assert_eq!(
+            builtin_op(ArithmeticOp::SUB, vec![
+                bigint("9223372036854775808"),
+                LVal::int(1)
+            ]),
+            LVal::int(9223372036854775807)
+        );
+        assert_eq!(
+            builtin_op(ArithmeticOp::ADD, vec![
+                ratio(1, 2),
+                ratio(1, 2)
+            ]),
+            LVal::int(1)
+        )
+    }
+
+    #[test]
+    fn builtin_op_rational() {
+        assert_eq!(
+            builtin_op(ArithmeticOp::ADD, vec![
+                ratio(1, 3),
+                ratio(1, 6)
+            ]),
+            ratio(1, 2)
+        );
+        assert_eq!(
+            builtin_op(ArithmeticOp::MUL, vec![
+                ratio(1, 2),
+                LVal::num(1.5)
+            ]),
+            LVal::num(0.75)
+        )
+    }
+
+    #[test]
+    fn builtin_int_op_div() {
+        assert_eq!(
+            builtin_int_op(IntegerOp::DIV, vec![
+                LVal::int(-7),
+                LVal::int(2)
+            ]),
+            LVal::int(-4)
+        )
+    }
+
+    #[test]
+    fn builtin_int_op_quot_rem() {
+        assert_eq!(
+            builtin_int_op(IntegerOp::QUOT, vec![
+                LVal::int(-7),
+                LVal::int(2)
+            ]),
+            LVal::int(-3)
+        );
+        assert_eq!(
+            builtin_int_op(IntegerOp::REM, vec![
+                LVal::int(-7),
+                LVal::int(2)
+            ]),
+            LVal::int(-1)
+        )
+    }
 }
\ No newline at end of file
@@ -1,33 +1,40 @@
 use lval::LVal;
 use lenv::LEnv;
+use eval::eval;
+use parser::Parser;
 use builtin::conditions::*;
 use builtin::env::*;
+use builtin::error::*;
+use builtin::format::*;
 use builtin::list::*;
+use builtin::map::*;
 use builtin::math::*;
+use builtin::string::*;
+use builtin::flags::*;
 
 
 macro_rules! builtin_assert(
 
     ($func:expr; ASSERT LENGTH EQ, $length:expr, $expected:expr) => {
         if $length < $expected {
-            err!("`{}` called with too few arguments: expected {}, got {}",
+            err!(kind: "arity-error"; "`{}` called with too few arguments: expected {}, got {}",
                         $func, $expected, $length)
         } else if $length > $expected {
-            err!("`{}` called with too many arguments: expected {}, got {}",
+            err!(kind: "arity-error"; "`{}` called with too many arguments: expected {}, got {}",
                         $func, $expected, $length)
         }
     };
 
     ($func:expr; ASSERT LENGTH GE, $length:expr, $expected:expr) => {
         if $length < $expected {
-            err!("`{}` called with too few arguments: expected at least {}, got {}",
+            err!(kind: "arity-error"; "`{}` called with too few arguments: expected at least {}, got {}",
                         $func, $expected, $length)
         }
     };
 
     ($func:expr; ASSERT LENGTH LE, $length:expr, $expected:expr) => {
         if $length > $expected {
-            err!("`{}` called with too few arguments: expected at least {}, got {}",
+            err!(kind: "arity-error"; "`{}` called with too few arguments: expected at least {}, got {}",
                         $func, $expected, $length)
         }
     };
@@ -35,7 +42,7 @@ macro_rules! builtin_assert(
     // FIXME: Find a solution without typ_name
     ($func:expr; ASSERT TYPE: $element:expr, $pos:expr, $typ:ident) => {
         if !lval_is!($element, $typ) {
-            err!("`{}` called with wrong type for argument {}: expected {}, got {}: `{}`",
+            err!(kind: "type-error"; "`{}` called with wrong type for argument {}: expected {}, got {}: `{}`",
                 //$func, $pos + 1, $typ_name, $element.type_name(), $element)
                 $func, $pos + 1, lval_type_name!($typ), $element.type_name(), $element)
         }
@@ -64,8 +71,8 @@ macro_rules! builtin_assert(
     ($func:expr; $args:ident [ $i:expr ] != {}) => {
         {
             builtin_assert!($func; ASSERT TYPE: $args[$i], $i, qexpr);
-            if $args[$i].as_values().len() == 0 {
-                err!("`{}` called with empty q-expr", $func)
+            if $args[$i].as_list().is_empty() {
+                err!(kind: "value-error"; "`{}` called with empty q-expr", $func)
             }
         }
     };
@@ -79,31 +86,85 @@ macro_rules! builtin_assert(
 
 mod conditions;
 pub mod env;
+mod error;
+mod format;
 mod list;
-mod math;
+mod map;
+pub mod math;
+mod string;
+
+
+/// What builtins do, recorded for each builtin by `initialize`
+///
+/// The passes over code and the virtual machine check these with
+/// `LBuiltin::has`.
+pub mod flags {
+    /// May define variables in the environment it's called in, either
+    /// directly or by running code that isn't known in advance
+    ///
+    /// The virtual machine reloads its slots after calling one of these, and
+    /// lambda bodies using one don't get their outer variables resolved to
+    /// lexical addresses, as a new variable could shadow them.
+    pub const WRITES_ENV: u32 = 1;
+
+    /// Binds the names in its first Q-Expr argument
+    ///
+    /// The optimizer doesn't fold code rebinding the builtins it folds.
+    pub const BINDS_NAMES: u32 = 2;
+
+    /// Always gives the same result for the same arguments and has no side
+    /// effects
+    ///
+    /// Calls of these on constants are folded by the optimizer.
+    pub const PURE: u32 = 4;
+
+    /// Evaluates its Q-Expr arguments in the calling environment, like `if`
+    /// does with its branches
+    pub const EVALUATES_BRANCHES: u32 = 8;
+}
+
+
+/// The standard prelude, written in mlisp
+const PRELUDE: &'static str = include_str!("prelude.lisp");
 
 
-pub fn initialize(env: &mut LEnv) {
+/// Put the builtins into `env` and, if `prelude` is set, evaluate the
+/// standard prelude
+pub fn initialize(env: &mut LEnv, prelude: bool) {
     // Environment
-    env.put(LVal::sym("\\"),    LVal::func(builtin_lambda));
-    env.put(LVal::sym("def"),   LVal::func(builtin_def));
-    env.put(LVal::sym("="),     LVal::func(builtin_put));
-    env.put(LVal::sym("eval"),  LVal::func(builtin_eval));
-    env.put(LVal::sym("load"),  LVal::func(builtin_load));
-    env.put(LVal::sym("error"), LVal::func(builtin_error));
+    env.put(LVal::sym("\\"),    LVal::func_with(builtin_lambda, BINDS_NAMES));
+    env.put(LVal::sym("macro"), LVal::func_with(builtin_macro, BINDS_NAMES));
+    env.put(LVal::sym("defmacro"), LVal::func_with(builtin_defmacro, BINDS_NAMES));
+    env.put(LVal::sym("macroexpand"), LVal::func(builtin_macroexpand));
+    env.put(LVal::sym("macroexpand-1"), LVal::func(builtin_macroexpand_1));
+    env.put(LVal::sym("quasiquote"), LVal::func_with(builtin_quasiquote, WRITES_ENV));
+    env.put(LVal::sym("unquote"), LVal::func(builtin_unquote));
+    env.put(LVal::sym("unquote-splicing"), LVal::func(builtin_unquote_splicing));
+    env.put(LVal::sym("def"),   LVal::func_with(builtin_def, BINDS_NAMES));
+    env.put(LVal::sym("="),     LVal::func_with(builtin_put, WRITES_ENV | BINDS_NAMES));
+    env.put(LVal::sym("eval"),  LVal::func_with(builtin_eval, WRITES_ENV));
+    env.put(LVal::sym("load"),  LVal::func_with(builtin_load, WRITES_ENV));
     env.put(LVal::sym("println"), LVal::func(builtin_println));
 
+    // Errors
+    env.put(LVal::sym("error"),         LVal::func(builtin_error));
+    env.put(LVal::sym("try"),           LVal::func_with(builtin_try, WRITES_ENV));
+    env.put(LVal::sym("error-kind"),    LVal::func(builtin_error_kind));
+    env.put(LVal::sym("error-message"), LVal::func(builtin_error_message));
+    env.put(LVal::sym("error-data"),    LVal::func(builtin_error_data));
+    env.put(LVal::sym("error-backtrace"), LVal::func(builtin_error_backtrace));
+
     // Conditions
-    env.put(LVal::sym("<"),     LVal::func(builtin_lt));
-    env.put(LVal::sym("<="),    LVal::func(builtin_le));
-    env.put(LVal::sym(">="),    LVal::func(builtin_ge));
-    env.put(LVal::sym(">"),     LVal::func(builtin_gt));
-    env.put(LVal::sym("=="),    LVal::func(builtin_eq));
-    env.put(LVal::sym("!="),    LVal::func(builtin_neq));
-    env.put(LVal::sym("if"),    LVal::func(builtin_if));
-    env.put(LVal::sym("or"),    LVal::func(builtin_or));
-    env.put(LVal::sym("and"),   LVal::func(builtin_and));
-    env.put(LVal::sym("not"),   LVal::func(builtin_not));
+    env.put(LVal::sym("<"),     LVal::func_with(builtin_lt, PURE));
+    env.put(LVal::sym("<="),    LVal::func_with(builtin_le, PURE));
+    env.put(LVal::sym(">="),    LVal::func_with(builtin_ge, PURE));
+    env.put(LVal::sym(">"),     LVal::func_with(builtin_gt, PURE));
+    env.put(LVal::sym("=="),    LVal::func_with(builtin_eq, PURE));
+    env.put(LVal::sym("!="),    LVal::func_with(builtin_neq, PURE));
+    env.put(LVal::sym("if"),    LVal::func_with(builtin_if, EVALUATES_BRANCHES));
+    env.put(LVal::sym("or"),    LVal::func_with(builtin_or, PURE));
+    env.put(LVal::sym("and"),   LVal::func_with(builtin_and, PURE));
+    env.put(LVal::sym("not"),   LVal::func_with(builtin_not, PURE));
 
     // Lists
     env.put(LVal::sym("head"),  LVal::func(builtin_head));
@@ -111,13 +172,72 @@ pub fn initialize(env: &mut LEnv) {
     env.put(LVal::sym("list"),  LVal::func(builtin_list));
     env.put(LVal::sym("join"),  LVal::func(builtin_join));
     env.put(LVal::sym("cons"),  LVal::func(builtin_cons));
+    env.put(LVal::sym("map"),   LVal::func(builtin_map));
+    env.put(LVal::sym("filter"), LVal::func(builtin_filter));
+    env.put(LVal::sym("foldl"), LVal::func(builtin_foldl));
+    env.put(LVal::sym("foldr"), LVal::func(builtin_foldr));
+    env.put(LVal::sym("reduce"), LVal::func(builtin_reduce));
+    env.put(LVal::sym("sort"),  LVal::func(builtin_sort));
+    env.put(LVal::sym("any"),   LVal::func(builtin_any));
+    env.put(LVal::sym("all"),   LVal::func(builtin_all));
+    env.put(LVal::sym("zip"),   LVal::func(builtin_zip));
+
+    // Maps
+    env.put(LVal::sym("hash-map"),  LVal::func(builtin_hash_map));
+    env.put(LVal::sym("get"),       LVal::func(builtin_get));
+    env.put(LVal::sym("assoc"),     LVal::func(builtin_assoc));
+    env.put(LVal::sym("dissoc"),    LVal::func(builtin_dissoc));
+    env.put(LVal::sym("keys"),      LVal::func(builtin_keys));
+    env.put(LVal::sym("vals"),      LVal::func(builtin_vals));
+    env.put(LVal::sym("contains?"), LVal::func(builtin_contains));
+    env.put(LVal::sym("merge"),     LVal::func(builtin_merge));
+
+    // Strings
+    env.put(LVal::sym("concat"),           LVal::func(builtin_concat));
+    env.put(LVal::sym("string-length"),    LVal::func(builtin_string_length));
+    env.put(LVal::sym("substring"),        LVal::func(builtin_substring));
+    env.put(LVal::sym("string-split"),     LVal::func(builtin_string_split));
+    env.put(LVal::sym("string-join"),      LVal::func(builtin_string_join));
+    env.put(LVal::sym("trim"),             LVal::func(builtin_trim));
+    env.put(LVal::sym("trim-left"),        LVal::func(builtin_trim_left));
+    env.put(LVal::sym("trim-right"),       LVal::func(builtin_trim_right));
+    env.put(LVal::sym("upcase"),           LVal::func(builtin_upcase));
+    env.put(LVal::sym("downcase"),         LVal::func(builtin_downcase));
+    env.put(LVal::sym("index-of"),         LVal::func(builtin_index_of));
+    env.put(LVal::sym("string-contains?"), LVal::func(builtin_string_contains));
+    env.put(LVal::sym("starts-with?"),     LVal::func(builtin_starts_with));
+    env.put(LVal::sym("ends-with?"),       LVal::func(builtin_ends_with));
+    env.put(LVal::sym("replace"),          LVal::func(builtin_replace));
+    env.put(LVal::sym("format"),           LVal::func(builtin_format));
+    env.put(LVal::sym("printf"),           LVal::func(builtin_printf));
 
     // Math
-    env.put(LVal::sym("+"),     LVal::func(builtin_add));
-    env.put(LVal::sym("-"),     LVal::func(builtin_sub));
-    env.put(LVal::sym("*"),     LVal::func(builtin_mul));
-    env.put(LVal::sym("/"),     LVal::func(builtin_div));
-    env.put(LVal::sym("%"),     LVal::func(builtin_mod));
-    env.put(LVal::sym("min"),   LVal::func(builtin_min));
-    env.put(LVal::sym("max"),   LVal::func(builtin_max));
+    env.put(LVal::sym("+"),     LVal::func_with(builtin_add, PURE));
+    env.put(LVal::sym("-"),     LVal::func_with(builtin_sub, PURE));
+    env.put(LVal::sym("*"),     LVal::func_with(builtin_mul, PURE));
+    env.put(LVal::sym("/"),     LVal::func_with(builtin_div, PURE));
+    env.put(LVal::sym("%"),     LVal::func_with(builtin_mod, PURE));
+    env.put(LVal::sym("min"),   LVal::func_with(builtin_min, PURE));
+    env.put(LVal::sym("max"),   LVal::func_with(builtin_max, PURE));
+    env.put(LVal::sym("div"),   LVal::func_with(builtin_div_int, PURE));
+    env.put(LVal::sym("quot"),  LVal::func_with(builtin_quot, PURE));
+    env.put(LVal::sym("rem"),   LVal::func_with(builtin_rem, PURE));
+
+    if prelude {
+        load_prelude(env);
+    }
+}
+
+
+fn load_prelude(env: &mut LEnv) {
+    let ast = match Parser::parse(PRELUDE, "<prelude>") {
+        Ok(ast) => ast,
+        Err(err) => panic!("cannot parse the prelude: {:?}", err)
+    };
+
+    for expr in LVal::from_ast(ast).into_values() {
+        if let LVal::Err(err) = eval(env, expr) {
+            panic!("cannot evaluate the prelude: {}", err)
+        }
+    }
 }
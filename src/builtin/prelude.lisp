@@ -0,0 +1,71 @@
+; The standard prelude
+;
+; Evaluated by `builtin::initialize` unless it's disabled with `--no-prelude`.
+
+; --- Functions ----------------------------------------------------------------
+
+; Define a function: (fun {name args...} {body})
+(def {fun} (\ {f b} {def (head f) (\ (tail f) b)}))
+
+; Call a function with a list of arguments and vice versa
+(fun {unpack f l} {eval (join (list f) l)})
+(fun {pack f ... xs} {f xs})
+(def {curry} unpack)
+(def {uncurry} pack)
+
+; Evaluate expressions in order, returning the last value
+(fun {do ... l} {if (== l {}) {nil} {last l}})
+
+; Evaluate a body in a new scope
+(fun {let b} {((\ {_} b) ())})
+
+(fun {flip f a b} {f b a})
+(fun {comp f g x} {f (g x)})
+
+; --- Lists --------------------------------------------------------------------
+
+(fun {fst l} {eval (head l)})
+(fun {snd l} {eval (head (tail l))})
+(fun {trd l} {eval (head (tail (tail l)))})
+
+(fun {len l} {foldl (\ {acc _} {+ acc 1}) 0 l})
+(fun {nth n l} {if (== n 0) {fst l} {nth (- n 1) (tail l)}})
+(fun {last l} {nth (- (len l) 1) l})
+
+(fun {take n l} {if (== n 0) {{}} {join (head l) (take (- n 1) (tail l))}})
+(fun {drop n l} {if (== n 0) {l} {drop (- n 1) (tail l)}})
+(fun {split n l} {list (take n l) (drop n l)})
+
+(fun {elem x l} {any (\ {y} {== x y}) l})
+(fun {reverse l} {foldl (\ {acc x} {cons x acc}) {} l})
+
+(fun {sum l} {foldl + 0 l})
+(fun {product l} {foldl * 1 l})
+
+; --- Conditionals -------------------------------------------------------------
+
+(defmacro {when c body} `(if ,@c {,@body}))
+(defmacro {unless c body} `(if ,@c {} {,@body}))
+
+(def {otherwise} true)
+
+; (cond {test value} ...) evaluates the value of the first clause whose test
+; is true
+(fun {cond-expand clauses}
+  {do
+    (= {clause} (fst (fst clauses)))
+    `(if ,@(head clause) {,@(tail clause)} {cond ,@(map fst (tail clauses))})})
+
+(defmacro {cond ... clauses}
+  {if (== clauses {}) {{}} {cond-expand clauses}})
+
+(def {select} cond)
+
+; (case x {value result} ...) returns the result for the first value equal
+; to `x`
+(fun {case x ... cs}
+  {if (== cs {})
+    {error "no case found"}
+    {if (== x (fst (fst cs)))
+      {snd (fst cs)}
+      {unpack case (join (list x) (tail cs))}}})
@@ -0,0 +1,340 @@
+use std::fmt;
+use lval::LVal;
+use lenv::LEnv;
+
+
+/// Get a character index from an integer argument
+fn to_index(func: &str, value: &LVal) -> Result<usize, LVal> {
+    match *value {
+        LVal::Int(i) if i >= 0 => Ok(i as usize),
+        _ => Err(LVal::err_kind("index-error", format!(
+            "`{}` called with an invalid index: `{}`", func, value)))
+    }
+}
+
+
+pub fn builtin_concat(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("concat"; args[*] is string);
+
+    let mut concatenated = String::new();
+    for arg in args {
+        concatenated.push_str(&arg.into_str());
+    }
+
+    LVal::string(concatenated)
+}
+
+
+/// Get the number of characters in a string
+pub fn builtin_string_length(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("string-length"; args.len() == 1);
+    builtin_assert!("string-length"; args[0] is string);
+
+    LVal::int(args[0].as_str().chars().count() as i64)
+}
+
+
+/// Get the characters from `start` up to `end`: `substring s start [end]`
+pub fn builtin_substring(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("substring"; args.len() >= 2);
+    builtin_assert!("substring"; args.len() <= 3);
+    builtin_assert!("substring"; args[0] is string);
+    builtin_assert!("substring"; args[1] is integer);
+
+    if args.len() == 3 {
+        builtin_assert!("substring"; args[2] is integer);
+    }
+
+    let string = args.remove(0).into_str();
+    let length = string.chars().count();
+
+    let start = match to_index("substring", &args[0]) {
+        Ok(start) => start,
+        Err(err) => return err
+    };
+    let end = if args.len() == 2 {
+        match to_index("substring", &args[1]) {
+            Ok(end) => end,
+            Err(err) => return err
+        }
+    } else {
+        length
+    };
+
+    if start > end || end > length {
+        err!(kind: "index-error"; "`substring` called with a range out of bounds: {}..{} \
+                                   for a string of length {}", start, end, length)
+    }
+
+    LVal::string(string.chars().skip(start).take(end - start).collect())
+}
+
+
+/// Split a string at each occurence of a separator
+///
+/// An empty separator splits the string into its characters.
+pub fn builtin_string_split(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("string-split"; args.len() == 2);
+    builtin_assert!("string-split"; args[*] is string);
+
+    let string = args[0].as_str();
+    let separator = args[1].as_str();
+
+    if separator.is_empty() {
+        LVal::qexpr_of(string.chars().map(|c| LVal::string(c.to_string())).collect())
+    } else {
+        LVal::qexpr_of(string.split(separator).map(|part| LVal::str(part)).collect())
+    }
+}
+
+
+/// Join a list of strings with a separator: `string-join {strings} sep`
+pub fn builtin_string_join(_: &mut LEnv, mut args: Vec<LVal>) -> LVal {
+    builtin_assert!("string-join"; args.len() == 2);
+    builtin_assert!("string-join"; args[0] is qexpr);
+    builtin_assert!("string-join"; args[1] is string);
+
+    let separator = args.remove(1).into_str();
+    let parts = args.remove(0).into_values();
+
+    let mut strings = vec![];
+    for part in parts {
+        match part {
+            part @ LVal::Str(..) => strings.push(part.into_str()),
+            other => err!(kind: "type-error"; "`string-join` called with a list containing \
+                                               {}: `{}`", other.type_name(), other)
+        }
+    }
+
+    LVal::string(strings.connect(&separator))
+}
+
+
+enum Trim {
+    Both,
+    Left,
+    Right
+}
+
+impl fmt::Display for Trim {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Trim::Both  => write!(f, "{}", "trim"),
+            Trim::Left  => write!(f, "{}", "trim-left"),
+            Trim::Right => write!(f, "{}", "trim-right")
+        }
+    }
+}
+
+
+/// Remove whitespace from the start and/or the end of a string
+pub fn builtin_trim_op(op: Trim, args: Vec<LVal>) -> LVal {
+    builtin_assert!(op; args.len() == 1);
+    builtin_assert!(op; args[0] is string);
+
+    let string = args[0].as_str();
+
+    LVal::str(match op {
+        Trim::Both  => string.trim(),
+        Trim::Left  => string.trim_left(),
+        Trim::Right => string.trim_right()
+    })
+}
+
+
+pub fn builtin_trim(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_trim_op(Trim::Both, args)
+}
+
+
+pub fn builtin_trim_left(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_trim_op(Trim::Left, args)
+}
+
+
+pub fn builtin_trim_right(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_trim_op(Trim::Right, args)
+}
+
+
+enum Case {
+    Upper,
+    Lower
+}
+
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Case::Upper => write!(f, "{}", "upcase"),
+            Case::Lower => write!(f, "{}", "downcase")
+        }
+    }
+}
+
+
+/// Convert a string to upper or lower case
+///
+/// Characters may change their length, e.g. `ß` becomes `SS`.
+pub fn builtin_case(case: Case, args: Vec<LVal>) -> LVal {
+    builtin_assert!(case; args.len() == 1);
+    builtin_assert!(case; args[0] is string);
+
+    let chars = args[0].as_str().chars();
+
+    LVal::string(match case {
+        Case::Upper => chars.flat_map(|c| c.to_uppercase()).collect(),
+        Case::Lower => chars.flat_map(|c| c.to_lowercase()).collect()
+    })
+}
+
+
+pub fn builtin_upcase(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_case(Case::Upper, args)
+}
+
+
+pub fn builtin_downcase(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_case(Case::Lower, args)
+}
+
+
+/// Get the character index of the first occurence of `needle` or `nil`
+pub fn builtin_index_of(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("index-of"; args.len() == 2);
+    builtin_assert!("index-of"; args[*] is string);
+
+    let string = args[0].as_str();
+
+    match string.find(args[1].as_str()) {
+        Some(byte) => LVal::int(string[..byte].chars().count() as i64),
+        None => LVal::nil()
+    }
+}
+
+
+enum Search {
+    Contains,
+    StartsWith,
+    EndsWith
+}
+
+impl fmt::Display for Search {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Search::Contains   => write!(f, "{}", "string-contains?"),
+            Search::StartsWith => write!(f, "{}", "starts-with?"),
+            Search::EndsWith   => write!(f, "{}", "ends-with?")
+        }
+    }
+}
+
+
+pub fn builtin_search(search: Search, args: Vec<LVal>) -> LVal {
+    builtin_assert!(search; args.len() == 2);
+    builtin_assert!(search; args[*] is string);
+
+    let string = args[0].as_str();
+    let needle = args[1].as_str();
+
+    LVal::Bool(match search {
+        Search::Contains   => string.contains(needle),
+        Search::StartsWith => string.starts_with(needle),
+        Search::EndsWith   => string.ends_with(needle)
+    })
+}
+
+
+pub fn builtin_string_contains(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_search(Search::Contains, args)
+}
+
+
+pub fn builtin_starts_with(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_search(Search::StartsWith, args)
+}
+
+
+pub fn builtin_ends_with(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_search(Search::EndsWith, args)
+}
+
+
+/// Replace all occurences of `from` with `to`: `replace s from to`
+pub fn builtin_replace(_: &mut LEnv, args: Vec<LVal>) -> LVal {
+    builtin_assert!("replace"; args.len() == 3);
+    builtin_assert!("replace"; args[*] is string);
+
+    if args[1].as_str().is_empty() {
+        err!(kind: "value-error"; "`replace` called with an empty string to replace")
+    }
+
+    LVal::string(args[0].as_str().replace(args[1].as_str(), args[2].as_str()))
+}
+
+
+// --- Tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use lval::LVal;
+    use lenv::LEnv;
+    use super::*;
+
+    #[test]
+    fn builtin_unicode_length() {
+        assert_eq!(
+            builtin_string_length(&mut LEnv::new(), vec![LVal::str("größe")]),
+            LVal::int(5)
+        )
+    }
+
+    #[test]
+    fn builtin_substring_chars() {
+        assert_eq!(
+            builtin_substring(&mut LEnv::new(), vec![LVal::str("größe"), LVal::int(2),
+                                                     LVal::int(4)]),
+            LVal::str("öß")
+        );
+        assert_eq!(
+            builtin_substring(&mut LEnv::new(), vec![LVal::str("abc"), LVal::int(2),
+                                                     LVal::int(4)]),
+            LVal::err_kind("index-error", "`substring` called with a range out of bounds: \
+                                           2..4 for a string of length 3".to_string())
+        )
+    }
+
+    #[test]
+    fn builtin_split_join() {
+        let parts = builtin_string_split(&mut LEnv::new(), vec![LVal::str("a, b, c"),
+                                                                LVal::str(", ")]);
+        assert_eq!(
+            parts,
+            LVal::qexpr_of(vec![LVal::str("a"), LVal::str("b"), LVal::str("c")])
+        );
+        assert_eq!(
+            builtin_string_join(&mut LEnv::new(), vec![parts, LVal::str("-")]),
+            LVal::str("a-b-c")
+        )
+    }
+
+    #[test]
+    fn builtin_case_conversion() {
+        assert_eq!(
+            builtin_upcase(&mut LEnv::new(), vec![LVal::str("straße")]),
+            LVal::str("STRASSE")
+        )
+    }
+
+    #[test]
+    fn builtin_search_and_replace() {
+        assert_eq!(
+            builtin_index_of(&mut LEnv::new(), vec![LVal::str("äbc"), LVal::str("c")]),
+            LVal::int(2)
+        );
+        assert_eq!(
+            builtin_replace(&mut LEnv::new(), vec![LVal::str("a-b-c"), LVal::str("-"),
+                                                   LVal::str("+")]),
+            LVal::str("a+b+c")
+        )
+    }
+}
@@ -1,147 +1,857 @@
-use lval::{LVal, LBuiltin};
+use std::rc::Rc;
+use lval::{LVal, LBuiltin, LError, Span};
 use lenv::LEnv;
+use fuel;
+use stack;
+use stack::StackFrame;
 use util::stringify_vec;
 
 
+/// The next step of the evaluation
+///
+/// Instead of recursing into a function body, `apply` hands it back to the
+/// loop in `eval`. That way calls in tail position run in constant stack
+/// space.
+enum Step {
+    /// The evaluation has finished
+    Done(LVal),
+
+    /// Continue by evaluating an expression in the current environment
+    Eval(LVal),
+
+    /// Continue by evaluating a function body in a new environment
+    Call(LEnv, LVal, StackFrame)
+}
+
+
+/// The number of calls kept in the backtrace of `depth_exceeded` errors
+const DEPTH_BACKTRACE: usize = 10;
+
+/// The error for exceeding the evaluation depth (see `stack::set_max_depth`)
+/// while calling `name`
+pub fn depth_exceeded(name: &str) -> LVal {
+    let mut err = LError::new("stack-overflow", format!("stack depth exceeded in `{}`", name));
+
+    // The whole stack would only repeat the same few calls
+    let mut backtrace = stack::backtrace();
+    backtrace.truncate(DEPTH_BACKTRACE);
+    err.backtrace = Some(backtrace);
+
+    LVal::Err(err)
+}
+
 /// Evaluate a lvalue
+///
+/// Errors get a backtrace of the calls in progress when they are first seen.
 pub fn eval(env: &mut LEnv, node: LVal) -> LVal {
-    match node {
-        LVal::SExpr(_) => eval_sexpr(env, node),
-        LVal::Sym(ref name) => env.get(&name),
-        node => node
+    if !stack::enter_eval() {
+        return match stack::innermost() {
+            Some(frame) => depth_exceeded(&frame.name),
+            None => depth_exceeded("<toplevel>")
+        }
+    }
+
+    let depth = stack::depth();
+    let mut result = eval_loop(env, node, depth);
+
+    if let LVal::Err(ref mut err) = result {
+        if err.backtrace.is_none() {
+            err.backtrace = Some(stack::backtrace());
+        }
+    }
+
+    // Leave the function entered by this loop, if any
+    stack::truncate(depth);
+    stack::leave_eval();
+
+    result
+}
+
+fn eval_loop(env: &mut LEnv, node: LVal, depth: usize) -> LVal {
+    // The environment changes when calling a function in tail position
+    let mut env = env.clone();
+    let mut node = node;
+
+    loop {
+        let step = match node {
+            LVal::SExpr(values, span) => locate(eval_sexpr(&mut env, &values, &span), &span),
+            LVal::Sym(name) => return env.get(name),
+            LVal::Local(name, depth, slot) => return env.get_local(name, depth, slot),
+            node => return node
+        };
+
+        match step {
+            Step::Done(value) => return value,
+            Step::Eval(next) => node = next,
+            Step::Call(lenv, body, frame) => {
+                // A tail call replaces the frame of the current function
+                if stack::depth() > depth {
+                    stack::replace_top(frame);
+                } else {
+                    stack::push(frame);
+                }
+
+                env = lenv;
+                node = body;
+            }
+        }
+    }
+}
+
+/// Attach the location of an S-Expr to an error that doesn't have one yet
+fn locate(step: Step, span: &Span) -> Step {
+    match step {
+        Step::Done(LVal::Err(mut err)) => {
+            if err.location.is_none() {
+                err.location = span.0.clone();
+            }
+
+            Step::Done(LVal::Err(err))
+        },
+        step => step
     }
 }
 
 /// Evaluate an expression
-fn eval_sexpr(env: &mut LEnv, node: LVal) -> LVal {
-    let values = node.into_values();
+fn eval_sexpr(env: &mut LEnv, values: &Vec<LVal>, span: &Span) -> Step {
+    if !fuel::consume() {
+        return Step::Done(fuel::out_of_fuel())
+    }
+
+    // Handle empty expression: Return S-Expr
+    if values.len() == 0 {
+        return Step::Done(LVal::sexpr())
+    }
+
+    // Name the call after the symbol the function is bound to
+    let frame = match values[0] {
+        LVal::Sym(name) | LVal::Local(name, _, _) => StackFrame::new(name.as_str(), span.0.clone()),
+        _ => StackFrame::new("<lambda>", span.0.clone())
+    };
+
+    // Evaluate the first value, macros get their arguments unevaluated
+    let first = eval(env, values[0].clone());
+
+    if let LVal::Macro { .. } = first {
+        let args = values[1..].iter()
+            .map(|val| LVal::qexpr_of(vec![val.clone()]))
+            .collect();
+
+        return match expand_macro(first, args) {
+            err @ LVal::Err(..) => Step::Done(err),
+            LVal::QExpr(code) => Step::Eval(LVal::SExpr(code.to_shared(), span.clone())),
+            code => Step::Eval(code)
+        }
+    }
 
     // Evaluate values & check for errors
-    let mut values: Vec<_> = values.into_iter()
-        .map(|val| eval(env, val))
+    let mut values: Vec<_> = values[1..].iter()
+        .map(|val| eval(env, val.clone()))
         .collect();
+    values.insert(0, first);
 
     // TODO: Return early if error is found instead of checking here
     for val in &values {
         if let LVal::Err(..) = *val {
-            return val.clone()
+            return Step::Done(val.clone())
         }
     }
 
-    // Handle empty expression: Return S-Expr
-    if values.len() == 0 {
-        return LVal::SExpr(vec![])
-    }
-
     // Handle single expression: Return the value itself
     if values.len() == 1 {
-        return values.remove(0)
+        return Step::Done(values.remove(0))
     }
 
     // Handle function calls
-    match values.remove(0) {
+    let func = values.remove(0);
+    apply(env, func, values, frame)
+}
 
-        // Call a lambda function
-        LVal::Function {
-            env: mut lenv,
-            mut formals,
-            body
-        } => {
-            let given = values.len();
-            let total = formals.len();
-
-            // Bind argument values to formal arguments
-            while values.len() > 0 {
-                if formals.len() == 0 {
-                    // No more arguments to bind
-                    err!("function (\\ {} {}) passed too many arguments: expecteded {}, got {}",
-                         stringify_vec(&formals), stringify_vec(&body), given, total)
-                }
+/// Expand a macro with the given arguments
+///
+/// The expansion is not evaluated yet, that's up to the caller.
+pub fn expand_macro(mac: LVal, args: Vec<LVal>) -> LVal {
+    match mac {
+        LVal::Macro { env: menv, mut formals, body } => {
+            let mut lenv = menv.copy();
 
-                let symbol = formals.remove(0);
+            if let Err(err) = bind_arguments(&mut lenv, formals.make_unique(), &body, args) {
+                return err
+            }
 
-                // Process varargs
-                if *symbol.as_sym() == "..." {
-                    if formals.len() != 1 {
-                        err!("invalid function arguments: `...` is not followed by a single symbol")
-                    }
+            if formals.len() > 0 {
+                err!(kind: "arity-error"; "macro called with too few arguments: missing {}",
+                     stringify_vec(&formals))
+            }
 
-                    // Bind vararg
-                    lenv.put(formals.remove(0), LVal::QExpr(values));
-                    break
-                }
+            eval(&mut lenv, LVal::SExpr(body, Span::none()))
+        },
+        other => err!(kind: "type-error"; "cannot expand {}: `{}`", other.type_name(), other)
+    }
+}
 
-                let value = values.remove(0);
-                lenv.put(symbol, value);
-            }
+/// Expand `form` once if it is a macro call
+///
+/// Returns `None` if `form` doesn't start with a macro.
+pub fn macroexpand_1(env: &mut LEnv, form: &Vec<LVal>) -> Option<LVal> {
+    if form.len() == 0 {
+        return None
+    }
+
+    let head = match form[0] {
+        LVal::Sym(name) => env.get(name),
+        LVal::Local(name, depth, slot) => env.get_local(name, depth, slot),
+        ref value => value.clone()
+    };
+
+    if let LVal::Macro { .. } = head {
+        let args = form[1..].iter()
+            .map(|val| LVal::qexpr_of(vec![val.clone()]))
+            .collect();
 
-            // If `...` has not been processed yet, bind it to an empty list
-            if formals.len() > 0 && *formals.get_mut(0).unwrap().as_sym() == "..." {
-                if formals.len() != 2 {
-                    err!("invalid function arguments: `...` is not followed by a single symbol")
+        Some(expand_macro(head, args))
+    } else {
+        None
+    }
+}
+
+/// Evaluate a quasiquoted template
+///
+/// Copies `template` while replacing `(unquote x)` with the value of `x` and
+/// `(unquote-splicing x)` with the elements of the Q-Expr `x` evaluates to.
+/// Nested quasiquotes are not treated specially.
+pub fn quasiquote(env: &mut LEnv, template: &Vec<LVal>) -> LVal {
+    match quasiquote_values(env, template) {
+        Ok(values) => LVal::qexpr_of(values),
+        Err(err) => err
+    }
+}
+
+fn quasiquote_values(env: &mut LEnv, template: &Vec<LVal>) -> Result<Vec<LVal>, LVal> {
+    let mut values = vec![];
+
+    for value in template {
+        match *value {
+            LVal::SExpr(ref children, ref span) => {
+                let unquote = unquote_kind(children);
+
+                match unquote {
+                    Some("unquote") => {
+                        match eval(env, children[1].clone()) {
+                            err @ LVal::Err(..) => return Err(err),
+                            value => values.push(value)
+                        }
+                    },
+                    Some(_) => {
+                        match eval(env, children[1].clone()) {
+                            spliced @ LVal::QExpr(..) => values.extend(spliced.into_values().into_iter()),
+                            err @ LVal::Err(..) => return Err(err),
+                            other => return Err(LVal::err_kind("type-error", format!(
+                                "`unquote-splicing` expects a q-expression, got {}: `{}`",
+                                other.type_name(), other)))
+                        }
+                    },
+                    None => {
+                        let children = try!(quasiquote_values(env, children));
+                        values.push(LVal::SExpr(Rc::new(children), span.clone()))
+                    }
                 }
+            },
+            LVal::QExpr(ref children) => {
+                values.push(LVal::qexpr_of(try!(quasiquote_values(env, &children.to_shared()))))
+            },
+            ref value => values.push(value.clone())
+        }
+    }
+
+    Ok(values)
+}
+
+/// Check whether `values` is an `(unquote x)` or `(unquote-splicing x)` form
+fn unquote_kind(values: &Vec<LVal>) -> Option<&'static str> {
+    if values.len() != 2 {
+        return None
+    }
+
+    match values[0] {
+        LVal::Sym(name) if name.as_str() == "unquote"          => Some("unquote"),
+        LVal::Sym(name) if name.as_str() == "unquote-splicing" => Some("unquote-splicing"),
+        _ => None
+    }
+}
 
-                // Delete `...`
-                formals.remove(0);
+/// Call a function with already evaluated arguments
+fn apply(env: &mut LEnv, func: LVal, values: Vec<LVal>, frame: StackFrame) -> Step {
+    match func {
 
-                let symbol = formals.remove(0);
-                let value = LVal::qexpr();
+        // Call a lambda function
+        LVal::Function {
+            env: fenv,
+            mut formals,
+            body
+        } => {
+            // Bind the arguments in a copy of the function's environment, so
+            // the function itself stays untouched
+            let mut lenv = fenv.copy();
 
-                lenv.put(symbol, value);
+            if let Err(err) = bind_arguments(&mut lenv, formals.make_unique(), &body, values) {
+                return Step::Done(err)
             }
 
             if formals.len() == 0 {
                 // If all arguments have been bound: execute
-                let parent: *mut LEnv = env;
-                lenv.parent = Some(parent);
-                eval(&mut lenv, LVal::SExpr(body))
+                // Errors in the body point to the call
+                let span = Span(frame.location.clone());
+                Step::Call(lenv, LVal::SExpr(body, span), frame)
             } else {
                 // Else: Return partially evaluated function
-                LVal::Function {
+                Step::Done(LVal::Function {
                     env: lenv,
                     formals: formals,
                     body: body
-                }
+                })
             }
         },
 
         // Call a builtin
-        LVal::Builtin(LBuiltin(f)) => {
+        LVal::Builtin(LBuiltin(f, _)) => {
             // Call with builtin operator
-            f(env, values)
+            builtin_step(call_builtin(f, env, values), &frame)
         },
 
         // FIXME: Why is this needed? Why may a symbol not be already evaluated?
-        LVal::Sym(ref name) => {
-            if let LVal::Builtin(LBuiltin(f)) = env.get(&name) {
-                f(env, values)
+        LVal::Sym(name) => {
+            if let LVal::Builtin(LBuiltin(f, _)) = env.get(name) {
+                builtin_step(call_builtin(f, env, values), &frame)
             }
             else {
-                err!("first element is not a function: {}", name)
+                Step::Done(LVal::err_kind("type-error", format!(
+                    "first element is not a function: {}", name)))
             }
         },
 
-        first => err!("first element is not a function but {}: `{}`",
-                      first.type_name(), first)
+        first => Step::Done(LVal::err_kind("type-error", format!(
+            "first element is not a function but {}: `{}`", first.type_name(), first)))
+    }
+}
+
+/// Call a builtin, if there's fuel left for it
+pub fn call_builtin(f: fn(&mut LEnv, Vec<LVal>) -> LVal, env: &mut LEnv, values: Vec<LVal>) -> LVal {
+    if !fuel::consume() {
+        return fuel::out_of_fuel()
+    }
+
+    f(env, values)
+}
+
+/// Call a function with already evaluated arguments and return its result
+///
+/// Unlike `apply`, the function body is evaluated right away. Used by
+/// builtins that need to call back into mlisp code.
+pub fn call(env: &mut LEnv, func: LVal, values: Vec<LVal>) -> LVal {
+    let frame = StackFrame::new("<lambda>", None);
+
+    match apply(env, func, values, frame) {
+        Step::Done(value) => value,
+        Step::Eval(next) => eval(env, next),
+        Step::Call(mut lenv, body, frame) => {
+            let depth = stack::depth();
+            stack::push(frame);
+
+            let result = eval(&mut lenv, body);
+            stack::truncate(depth);
+
+            result
+        }
+    }
+}
+
+/// Bind argument values to the formal arguments of a lambda
+///
+/// Bound formals are removed from `formals`, so if there are any left, the
+/// function has only been partially applied.
+pub fn bind_arguments(lenv: &mut LEnv, formals: &mut Vec<LVal>, body: &Vec<LVal>,
+                      mut values: Vec<LVal>) -> Result<(), LVal> {
+    let given = values.len();
+    let total = formals.len();
+
+    while values.len() > 0 {
+        if formals.len() == 0 {
+            // No more arguments to bind
+            return Err(LVal::err_kind("arity-error", format!(
+                "function (\\ {} {}) passed too many arguments: expecteded {}, got {}",
+                stringify_vec(formals), stringify_vec(body), given, total)))
+        }
+
+        let symbol = formals.remove(0);
+
+        // Process varargs
+        if symbol.as_sym().as_str() == "..." {
+            if formals.len() != 1 {
+                return Err(LVal::err_kind("syntax-error", "invalid function arguments: \
+                                          `...` is not followed by a single symbol".to_string()))
+            }
+
+            // Bind vararg
+            lenv.put(formals.remove(0), LVal::qexpr_of(values));
+            break
+        }
+
+        let value = values.remove(0);
+        lenv.put(symbol, value);
+    }
+
+    // If `...` has not been processed yet, bind it to an empty list
+    if formals.len() > 0 && formals[0].as_sym().as_str() == "..." {
+        if formals.len() != 2 {
+            return Err(LVal::err_kind("syntax-error", "invalid function arguments: \
+                                      `...` is not followed by a single symbol".to_string()))
+        }
+
+        // Delete `...`
+        formals.remove(0);
+
+        let symbol = formals.remove(0);
+        let value = LVal::qexpr();
+
+        lenv.put(symbol, value);
+    }
+
+    Ok(())
+}
+
+/// Turn the result of a builtin into the next step
+///
+/// Builtins like `if` and `eval` don't evaluate their result themselves but
+/// return a tail call, so that the evaluation continues in this loop.
+fn builtin_step(result: LVal, frame: &StackFrame) -> Step {
+    match result {
+        LVal::TailCall(body) => Step::Eval(LVal::SExpr(body, Span(frame.location.clone()))),
+        value => Step::Done(value)
     }
 }
 
 
 #[cfg(test)]
 mod test {
+    use std::thread;
     use super::eval;
+    use stack;
     use lval::LVal;
     use lenv::LEnv;
+    use testing::{run, run_with};
 
     #[test]
     fn eval_not_a_symbol() {
         assert_eq!(
-            eval(&mut LEnv::new(), LVal::SExpr(vec![
+            eval(&mut LEnv::new(), LVal::sexpr_of(vec![
                 LVal::num(2.0),
                 LVal::num(2.0),
                 LVal::num(2.0),
             ])),
-            LVal::err("first element is not a function but a number: `2`".to_string())
+            LVal::err_kind("type-error",
+                           "first element is not a function but a number: `2`".to_string())
+        )
+    }
+
+    #[test]
+    fn eval_bool() {
+        assert_eq!(run(&["(== 1 1.0)"]), LVal::bool(true));
+        assert_eq!(run(&["(and true nil)"]), LVal::bool(false));
+        assert_eq!(run(&["(if nil {1} {2})"]), LVal::int(2));
+        assert_eq!(run(&["(if {} {1} {2})"]), LVal::int(2));
+        assert_eq!(run(&["(if \"\" {1} {2})"]), LVal::int(1));
+    }
+
+    #[test]
+    fn eval_closure() {
+        assert_eq!(
+            run(&[
+                "(def {adder} (\\ {x} {\\ {y} {+ x y}}))",
+                "(def {add5} (adder 5))",
+                "(def {x} 100)",
+                "(add5 3)"
+            ]),
+            LVal::int(8)
+        )
+    }
+
+    #[test]
+    fn eval_shared_values() {
+        // Modifying a value must not change the values it's shared with
+        assert_eq!(
+            run(&[
+                "(def {xs} {1 2 3})",
+                "(def {ys} (cons 0 xs))",
+                "(def {add} (\\ {x y} {+ x y}))",
+                "(def {inc} (add 1))",
+                "(list xs ys (inc 2) (add 2 3))"
+            ]),
+            LVal::qexpr_of(vec![
+                LVal::qexpr_of(vec![LVal::int(1), LVal::int(2), LVal::int(3)]),
+                LVal::qexpr_of(vec![LVal::int(0), LVal::int(1), LVal::int(2), LVal::int(3)]),
+                LVal::int(3),
+                LVal::int(5)
+            ])
+        )
+    }
+
+    #[test]
+    fn eval_list_builtins() {
+        assert_eq!(
+            run(&[
+                "(def {xs} {1 2 3})",
+                "(list (head xs) (tail xs) (cons 0 (tail xs)) (join {a} (tail xs) {b}) xs)"
+            ]),
+            LVal::qexpr_of(vec![
+                LVal::qexpr_of(vec![LVal::int(1)]),
+                LVal::qexpr_of(vec![LVal::int(2), LVal::int(3)]),
+                LVal::qexpr_of(vec![LVal::int(0), LVal::int(2), LVal::int(3)]),
+                LVal::qexpr_of(vec![LVal::sym("a"), LVal::int(2), LVal::int(3), LVal::sym("b")]),
+                LVal::qexpr_of(vec![LVal::int(1), LVal::int(2), LVal::int(3)])
+            ])
+        )
+    }
+
+    #[test]
+    fn eval_depth_limit() {
+        stack::set_max_depth(200);
+
+        let result = run(&[
+            "(def {down} (\\ {n} {+ 1 (down (- n 1))}))",
+            "(down 10)"
+        ]);
+        stack::set_max_depth(stack::DEFAULT_MAX_DEPTH);
+
+        assert_eq!(
+            result,
+            LVal::err_kind("stack-overflow", "stack depth exceeded in `down`".to_string())
+        )
+    }
+
+    #[test]
+    fn eval_default_depth() {
+        // With the stack `main` gives the evaluator, the default limit is
+        // reached before the native stack overflows
+        let evaluator = thread::Builder::new()
+            .stack_size(stack::stack_size(stack::DEFAULT_MAX_DEPTH))
+            .spawn(|| {
+                let result = run(&[
+                    "(def {down} (\\ {n} {+ 1 (down (- n 1))}))",
+                    "(down 10)"
+                ]);
+                assert_eq!(result.as_err().kind, "stack-overflow");
+
+                // Values nested deeper than the limit can still be compared
+                // and printed
+                let deep = run(&[
+                    "(def {nest} (\\ {n l} {if (== n 0) {l} {nest (- n 1) (list l)}}))",
+                    "(nest 20000 {})"
+                ]);
+                assert!(deep == deep.clone());
+                assert!(format!("{}", deep).contains("{...}"));
+            })
+            .unwrap();
+
+        assert!(evaluator.join().is_ok())
+    }
+
+    #[test]
+    fn eval_lexical_scope() {
+        assert_eq!(
+            run(&[
+                "(def {get-y} (\\ {_} {y}))",
+                "(def {f} (\\ {y} {get-y 0}))",
+                "(f 1)"
+            ]),
+            LVal::err_kind("unbound-symbol", "unbound symbol: y".to_string())
+        )
+    }
+
+    #[test]
+    fn eval_macro() {
+        assert_eq!(
+            run(&[
+                "(defmacro {unless c body} {join {if} c {{}} (list body)})",
+                "(unless (> 1 2) (+ 1 2))"
+            ]),
+            LVal::int(3)
+        )
+    }
+
+    #[test]
+    fn eval_macro_unevaluated_arguments() {
+        assert_eq!(
+            run(&[
+                "(defmacro {when c body} {join {if} c (list body)})",
+                "(when 0 (error \"unreachable\"))"
+            ]),
+            LVal::sexpr()
+        )
+    }
+
+    #[test]
+    fn eval_macroexpand() {
+        assert_eq!(
+            run(&[
+                "(defmacro {when c body} {join {if} c (list body)})",
+                "(macroexpand {when (> x 1) (println x)})"
+            ]),
+            LVal::qexpr_of(vec![
+                LVal::sym("if"),
+                LVal::sexpr_of(vec![LVal::sym(">"), LVal::sym("x"), LVal::int(1)]),
+                LVal::qexpr_of(vec![
+                    LVal::sexpr_of(vec![LVal::sym("println"), LVal::sym("x")])
+                ])
+            ])
+        )
+    }
+
+    #[test]
+    fn eval_quasiquote() {
+        assert_eq!(
+            run(&[
+                "(def {x xs} 2 {3 4})",
+                "`(+ 1 ,x ,@xs {,x})"
+            ]),
+            LVal::qexpr_of(vec![
+                LVal::sym("+"),
+                LVal::int(1),
+                LVal::int(2),
+                LVal::int(3),
+                LVal::int(4),
+                LVal::qexpr_of(vec![LVal::int(2)])
+            ])
+        )
+    }
+
+    #[test]
+    fn eval_quasiquote_macro() {
+        assert_eq!(
+            run(&[
+                "(defmacro {when c body} `(if ,@c {,@body}))",
+                "(when (> 2 1) (+ 1 2))"
+            ]),
+            LVal::int(3)
+        )
+    }
+
+    #[test]
+    fn eval_try_catch() {
+        assert_eq!(
+            run(&[
+                "(def {safe-div} (\\ {x y} {try {/ x y} (\\ {e} {error-kind e})}))",
+                "(safe-div 1 0)"
+            ]),
+            LVal::qexpr_of(vec![LVal::sym("division-by-zero")])
+        );
+        assert_eq!(
+            run(&["(try {error \"boom\" 42} (\\ {e} {error-data e}))"]),
+            LVal::int(42)
+        );
+        assert_eq!(run(&["(try {+ 1 2} (\\ {e} {0}))"]), LVal::int(3));
+
+        // The handler gets an ordinary value and can raise it again
+        assert_eq!(
+            run(&["(try {error \"boom\"} (\\ {e} {list (error-message e)}))"]),
+            LVal::qexpr_of(vec![LVal::str("boom")])
+        );
+        assert_eq!(
+            run(&["(try {error \"boom\"} (\\ {e} {error e}))"]),
+            LVal::err_kind("user-error", "boom".to_string())
+        )
+    }
+
+    #[test]
+    fn eval_error_inspection() {
+        assert_eq!(
+            run(&[
+                "(def {inspect} (\\ {e} {list (error-kind e) (error-message e) (error-data e)}))",
+                "(try {error {not-found} \"no such user\" 42} inspect)"
+            ]),
+            LVal::qexpr_of(vec![
+                LVal::qexpr_of(vec![LVal::sym("not-found")]),
+                LVal::str("no such user"),
+                LVal::int(42)
+            ])
+        );
+        assert_eq!(
+            run(&["(try {/ 1 0} (\\ {e} {list (error-kind e) (error-data e)}))"]),
+            LVal::qexpr_of(vec![LVal::qexpr_of(vec![LVal::sym("division-by-zero")]), LVal::nil()])
+        )
+    }
+
+    #[test]
+    fn eval_error_backtrace() {
+        assert_eq!(
+            run(&[
+                "(def {inner} (\\ {x} {/ x 0}))",
+                "(def {outer} (\\ {x} {+ 1 (inner x)}))",
+                "(def {name} (\\ {call} {get call \"function\"}))",
+                "(try {outer 1} (\\ {e} {map name (error-backtrace e)}))"
+            ]),
+            LVal::qexpr_of(vec![LVal::str("inner"), LVal::str("outer")])
+        )
+    }
+
+    #[test]
+    fn eval_try_finally() {
+        assert_eq!(
+            run(&[
+                "(def {cleaned} false)",
+                "(try {error \"boom\"} (\\ {e} {1}) {def {cleaned} true})",
+                "cleaned"
+            ]),
+            LVal::bool(true)
+        );
+        assert_eq!(
+            run(&["(try {1} (\\ {e} {2}) {error \"cleanup failed\"})"]),
+            LVal::err_kind("user-error", "cleanup failed".to_string())
+        )
+    }
+
+    #[test]
+    fn eval_backtrace() {
+        let result = run(&[
+            "(def {inner} (\\ {x} {+ x (error \"boom\")}))",
+            "(def {outer} (\\ {x} {+ 1 (inner x)}))",
+            "(outer 1)"
+        ]);
+
+        let names: Vec<_> = result.as_err().backtrace.as_ref().unwrap().iter()
+            .map(|frame| frame.name.clone())
+            .collect();
+        assert_eq!(names, vec!["inner".to_string(), "outer".to_string()])
+    }
+
+    #[test]
+    fn eval_backtrace_tail_call() {
+        let result = run(&[
+            "(def {fail} (\\ {x} {error \"boom\"}))",
+            "(def {outer} (\\ {x} {fail x}))",
+            "(outer 1)"
+        ]);
+
+        // `fail` has been called in tail position and replaced `outer`
+        let names: Vec<_> = result.as_err().backtrace.as_ref().unwrap().iter()
+            .map(|frame| frame.name.clone())
+            .collect();
+        assert_eq!(names, vec!["fail".to_string()])
+    }
+
+    #[test]
+    fn eval_error_location() {
+        let result = run(&["(+ 1\n   (/ 1 0))"]);
+        let location = result.as_err().location.as_ref().unwrap();
+
+        assert_eq!(format!("{}", location), "<test>:2:4")
+    }
+
+    #[test]
+    fn eval_higher_order_builtins() {
+        assert_eq!(
+            run(&["(map (\\ {x} {* x x}) {1 2 3})"]),
+            LVal::qexpr_of(vec![LVal::int(1), LVal::int(4), LVal::int(9)])
+        );
+        assert_eq!(
+            run(&["(filter (\\ {x} {> x 1}) {1 2 3})"]),
+            LVal::qexpr_of(vec![LVal::int(2), LVal::int(3)])
+        );
+        assert_eq!(run(&["(foldl - 0 {1 2 3})"]), LVal::int(-6));
+        assert_eq!(run(&["(foldr - 0 {1 2 3})"]), LVal::int(2));
+        assert_eq!(run(&["(reduce max {3 7 5})"]), LVal::int(7));
+        assert_eq!(run(&["(any (\\ {x} {> x 2}) {1 2 3})"]), LVal::bool(true));
+        assert_eq!(run(&["(all (\\ {x} {> x 2}) {1 2 3})"]), LVal::bool(false));
+        assert_eq!(
+            run(&["(zip {a b c} {1 2})"]),
+            LVal::qexpr_of(vec![
+                LVal::qexpr_of(vec![LVal::sym("a"), LVal::int(1)]),
+                LVal::qexpr_of(vec![LVal::sym("b"), LVal::int(2)])
+            ])
+        )
+    }
+
+    #[test]
+    fn eval_sort() {
+        assert_eq!(
+            run(&["(sort {3 1.5 2})"]),
+            LVal::qexpr_of(vec![LVal::num(1.5), LVal::int(2), LVal::int(3)])
+        );
+        assert_eq!(
+            run(&["(sort {\"b\" \"c\" \"a\"})"]),
+            LVal::qexpr_of(vec![LVal::str("a"), LVal::str("b"), LVal::str("c")])
+        );
+        assert_eq!(
+            run(&["(sort {1 3 2} (\\ {x y} {> x y}))"]),
+            LVal::qexpr_of(vec![LVal::int(3), LVal::int(2), LVal::int(1)])
+        );
+        assert_eq!(
+            run(&["(map (\\ {x} {/ 1 x}) {1 0})"]),
+            LVal::err_kind("division-by-zero", "division by zero!".to_string())
+        )
+    }
+
+    #[test]
+    fn eval_sort_incomparable() {
+        assert_eq!(run(&["(sort {1 \"a\"})"]).as_err().kind, "type-error");
+        assert_eq!(run(&["(sort {a b})"]).as_err().kind, "type-error");
+        assert_eq!(run(&["(sort {{1} {2}})"]).as_err().kind, "type-error");
+    }
+
+    #[test]
+    fn eval_prelude_keeps_builtins() {
+        // The prelude's list `split` must not hide the string builtin
+        assert_eq!(
+            run_with(true, &["(list (split 1 {1 2}) (string-split \"a,b\" \",\"))"]),
+            LVal::qexpr_of(vec![
+                LVal::qexpr_of(vec![
+                    LVal::qexpr_of(vec![LVal::int(1)]),
+                    LVal::qexpr_of(vec![LVal::int(2)])
+                ]),
+                LVal::qexpr_of(vec![LVal::str("a"), LVal::str("b")])
+            ])
+        )
+    }
+
+    #[test]
+    fn eval_prelude() {
+        assert_eq!(run_with(true, &["(len {1 2 3})"]), LVal::int(3));
+        assert_eq!(run_with(true, &["(nth 1 {1 2 3})"]), LVal::int(2));
+        assert_eq!(
+            run_with(true, &["(reverse {1 2 3})"]),
+            LVal::qexpr_of(vec![LVal::int(3), LVal::int(2), LVal::int(1)])
+        );
+        assert_eq!(
+            run_with(true, &[
+                "(fun {sign x} {cond {(< x 0) (- 1)} {(> x 0) 1} {otherwise 0}})",
+                "(list (sign (- 5)) (sign 0) (sign 7))"
+            ]),
+            LVal::qexpr_of(vec![LVal::int(-1), LVal::int(0), LVal::int(1)])
+        );
+        assert_eq!(run_with(true, &["(let {do (= {x} 2) (* x x)})"]), LVal::int(4));
+        assert_eq!(run(&["(len {1 2 3})"]),
+                   LVal::err_kind("unbound-symbol", "unbound symbol: len".to_string()))
+    }
+
+    #[test]
+    fn eval_tail_call() {
+        assert_eq!(
+            run(&[
+                "(def {countdown} (\\ {n} {if (== n 0) {n} {countdown (- n 1)}}))",
+                "(countdown 100000)"
+            ]),
+            LVal::int(0)
+        )
+    }
+
+    #[test]
+    fn eval_tail_call_through_eval() {
+        assert_eq!(
+            run(&[
+                "(def {loop} (\\ {n} {if (== n 0) {n} {eval {loop (- n 1)}}}))",
+                "(loop 100000)"
+            ]),
+            LVal::int(0)
         )
     }
 }
\ No newline at end of file
@@ -0,0 +1,114 @@
+//! Evaluation fuel
+//!
+//! Evaluating a S-Expr and calling a builtin each use up one step of fuel.
+//! When a budget is set and it runs out, evaluation stops with an
+//! `out-of-fuel` error. This bounds how long untrusted code can run, even if
+//! it loops forever in tail position.
+//!
+//! Use `eval_with_budget` to run code with a budget, the `--fuel` option
+//! sets one for each top-level expression.
+
+use std::cell::Cell;
+use lval::LVal;
+use lenv::LEnv;
+
+
+thread_local!(static FUEL: Cell<Option<u64>> = Cell::new(None));
+
+
+/// Set the number of steps evaluation may take, `None` for no limit
+pub fn set_budget(steps: Option<u64>) {
+    FUEL.with(|fuel| fuel.set(steps));
+}
+
+/// The number of steps left, `None` if there is no limit
+pub fn remaining() -> Option<u64> {
+    FUEL.with(|fuel| fuel.get())
+}
+
+/// Use up one step
+///
+/// Returns `false` if the budget is exhausted, the step must not be taken
+/// then.
+pub fn consume() -> bool {
+    FUEL.with(|fuel| match fuel.get() {
+        Some(0) => false,
+        Some(steps) => { fuel.set(Some(steps - 1)); true },
+        None => true
+    })
+}
+
+/// The error for an exhausted budget
+pub fn out_of_fuel() -> LVal {
+    LVal::err_kind("out-of-fuel", "evaluation step budget exhausted".to_string())
+}
+
+
+/// Evaluate `node` with `evaluate`, taking at most `steps` steps
+///
+/// `None` runs without a limit. The budget that was set before is restored
+/// afterwards.
+pub fn eval_with_budget(env: &mut LEnv, node: LVal, steps: Option<u64>,
+                        evaluate: &Fn(&mut LEnv, LVal) -> LVal) -> LVal {
+    let previous = remaining();
+
+    set_budget(steps);
+    let result = evaluate(env, node);
+    set_budget(previous);
+
+    result
+}
+
+
+// --- Tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use eval::eval;
+    use lval::LVal;
+    use lenv::LEnv;
+    use testing::{env, parse};
+    use super::{set_budget, remaining, consume, eval_with_budget, out_of_fuel};
+
+    fn run(env: &mut LEnv, line: &str, steps: u64) -> LVal {
+        eval_with_budget(env, parse(line), Some(steps), &eval)
+    }
+
+    #[test]
+    fn consume_budget() {
+        set_budget(Some(2));
+
+        assert!(consume());
+        assert!(consume());
+        assert!(!consume());
+        assert_eq!(remaining(), Some(0));
+
+        set_budget(None);
+        assert!(consume());
+        assert_eq!(remaining(), None);
+    }
+
+    #[test]
+    fn budget_stops_loop() {
+        let mut env = env(false);
+
+        run(&mut env, "(def {forever} (\\ {n} {forever (+ n 1)}))", 100);
+
+        assert_eq!(run(&mut env, "(forever 0)", 10000), out_of_fuel());
+        assert_eq!(remaining(), None);
+    }
+
+    #[test]
+    fn budget_is_restored() {
+        let mut env = env(false);
+
+        set_budget(Some(7));
+
+        // Two S-Exprs and two builtin calls, plus the line itself
+        assert_eq!(run(&mut env, "(+ 1 (* 2 3))", 5), LVal::int(7));
+        assert_eq!(run(&mut env, "(+ 1 (* 2 3))", 4), out_of_fuel());
+        assert_eq!(remaining(), Some(7));
+
+        set_budget(None);
+    }
+}
@@ -1,70 +1,235 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
+use std::rc::Rc;
 use lval::LVal;
+use symbol::Symbol;
 
 
+/// The contents of an environment
+///
+/// Variables are stored in slots which never move once they have been
+/// added, so a variable can be found by its slot number (see `get_local`).
+#[derive(Clone)]
+struct Frame {
+    parent: Option<LEnv>,
+    names: Vec<Symbol>,
+    values: Vec<LVal>,
+
+    /// Slots by name, only kept for the global environment as it's too big
+    /// for a linear search
+    index: Option<HashMap<Symbol, usize>>
+}
+
+impl Frame {
+    fn slot(&self, name: Symbol) -> Option<usize> {
+        match self.index {
+            Some(ref index) => index.get(&name).map(|&slot| slot),
+            None => self.names.iter().position(|&n| n == name)
+        }
+    }
+
+    fn insert(&mut self, name: Symbol, value: LVal) {
+        match self.slot(name) {
+            Some(slot) => self.values[slot] = value,
+            None => {
+                if let Some(ref mut index) = self.index {
+                    index.insert(name, self.names.len());
+                }
+
+                self.names.push(name);
+                self.values.push(value);
+            }
+        }
+    }
+}
+
+
+/// An environment
+///
+/// A `LEnv` is a handle to a shared frame: cloning it doesn't copy the
+/// contents. Lambdas keep a handle to the environment they have been created
+/// in, which makes closures work and gives lexical scoping.
+#[derive(Clone)]
 pub struct LEnv {
-    pub parent: Option<*mut LEnv>,
-    contents: HashMap<String, LVal>
+    frame: Rc<RefCell<Frame>>
 }
 
 impl LEnv {
     pub fn new() -> LEnv {
-        LEnv {
+        LEnv::from_frame(Frame {
             parent: None,
-            contents: HashMap::new()
+            names: vec![],
+            values: vec![],
+            index: Some(HashMap::new())
+        })
+    }
+
+    /// Create a new, empty environment on top of `parent`
+    pub fn with_parent(parent: &LEnv) -> LEnv {
+        LEnv::from_frame(Frame {
+            parent: Some(parent.clone()),
+            names: vec![],
+            values: vec![],
+            index: None
+        })
+    }
+
+    fn from_frame(frame: Frame) -> LEnv {
+        LEnv {
+            frame: Rc::new(RefCell::new(frame))
         }
     }
 
-    pub fn get(&self, key: &str) -> LVal {
-        if let Some(value) = self.contents.get(key) {
-            value.clone()
+    /// Create a new environment with a copy of this environment's contents
+    /// and the same parent
+    ///
+    /// The variables keep their slots.
+    pub fn copy(&self) -> LEnv {
+        LEnv::from_frame(self.frame.borrow().clone())
+    }
+
+    /// Create a new environment with a copy of this environment's contents
+    /// but without a parent
+    pub fn locals(&self) -> LEnv {
+        let mut frame = self.frame.borrow().clone();
+        frame.parent = None;
+
+        LEnv::from_frame(frame)
+    }
+
+    pub fn get(&self, key: Symbol) -> LVal {
+        let frame = self.frame.borrow();
+
+        if let Some(slot) = frame.slot(key) {
+            frame.values[slot].clone()
         } else {
             // Search in parent env, if possible
-            if let Some(ref env) = self.parent {
-                unsafe { (**env).get(key) }
+            if let Some(ref env) = frame.parent {
+                env.get(key)
             } else {
-                err!("unbound symbol: {}", key)
+                err!(kind: "unbound-symbol"; "unbound symbol: {}", key)
+            }
+        }
+    }
+
+    /// Get a variable by its lexical address
+    ///
+    /// `depth` is the number of parents to go up, `slot` the variable's slot
+    /// there. Falls back to looking up `key` by name if the address doesn't
+    /// hold `key`.
+    pub fn get_local(&self, key: Symbol, depth: usize, slot: usize) -> LVal {
+        let frame = self.frame.borrow();
+
+        if depth > 0 {
+            return match frame.parent {
+                Some(ref env) => env.get_local(key, depth - 1, slot),
+                None => self.get(key)
             }
         }
+
+        if slot < frame.names.len() && frame.names[slot] == key {
+            frame.values[slot].clone()
+        } else {
+            self.get(key)
+        }
+    }
+
+    /// Find the lexical address of a variable, see `get_local`
+    ///
+    /// Returns `None` for global and unbound variables.
+    pub fn address(&self, key: Symbol) -> Option<(usize, usize)> {
+        let frame = self.frame.borrow();
+
+        let parent = match frame.parent {
+            Some(ref parent) => parent,
+            None => return None
+        };
+
+        match frame.slot(key) {
+            Some(slot) => Some((0, slot)),
+            None => parent.address(key).map(|(depth, slot)| (depth + 1, slot))
+        }
     }
 
     pub fn put(&mut self, key: LVal, value: LVal) {
-        self.contents.insert(key.as_sym().clone(), value.clone());
+        self.frame.borrow_mut().insert(key.as_sym(), value);
     }
 
     pub fn def(&mut self, key: LVal, value: LVal) {
-        if let Some(ref env) = self.parent {
-            unsafe { (**env).def(key, value); }
+        let parent = self.frame.borrow().parent.clone();
+
+        if let Some(mut env) = parent {
+            env.def(key, value);
         } else {
             self.put(key, value);
         }
     }
 
-    pub fn look_up(&self, search: &LVal) -> Option<&str> {
-        self.contents.iter()
-            .find(|&(_, value)| value == search)
-            .map(|(key, _)| &**key)
+    /// The names defined in this environment itself, sorted
+    pub fn local_names(&self) -> Vec<Symbol> {
+        let mut names = self.frame.borrow().names.clone();
+        names.sort();
+        names
+    }
+
+    pub fn look_up(&self, search: &LVal) -> Option<Symbol> {
+        let frame = self.frame.borrow();
+
+        frame.values.iter()
+            .position(|value| value == search)
+            .map(|slot| frame.names[slot])
     }
 }
 
 impl fmt::Display for LEnv {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self.contents)
+        let frame = self.frame.borrow();
+
+        write!(f, "{{{}}}", frame.names.iter().zip(frame.values.iter())
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect::<Vec<_>>()
+            .connect(", "))
     }
 }
 
+// Environments are compared by identity, as a closure's environment usually
+// contains the closure itself
 impl PartialEq for LEnv {
     fn eq(&self, other: &LEnv) -> bool {
-        self.contents.eq(&other.contents)
+        let ptr_self:  *const RefCell<Frame> = &*self.frame;
+        let ptr_other: *const RefCell<Frame> = &*other.frame;
+        ptr_self == ptr_other
     }
 }
 
-impl Clone for LEnv {
-    fn clone(&self) -> LEnv {
-        LEnv {
-            parent: self.parent,
-            contents: self.contents.clone()
-        }
+
+// --- Tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use lval::LVal;
+    use symbol::Symbol;
+    use super::LEnv;
+
+    #[test]
+    fn lexical_address() {
+        let mut global = LEnv::new();
+        global.put(LVal::sym("g"), LVal::int(0));
+
+        let mut outer = LEnv::with_parent(&global);
+        outer.put(LVal::sym("a"), LVal::int(1));
+        outer.put(LVal::sym("b"), LVal::int(2));
+
+        let mut inner = LEnv::with_parent(&outer);
+        inner.put(LVal::sym("c"), LVal::int(3));
+
+        let b = Symbol::intern("b");
+        assert_eq!(inner.address(b), Some((1, 1)));
+        assert_eq!(inner.address(Symbol::intern("g")), None);
+        assert_eq!(inner.get_local(b, 1, 1), LVal::int(2));
+
+        // A wrong address falls back to the name
+        assert_eq!(inner.get_local(b, 0, 0), LVal::int(2));
     }
 }
\ No newline at end of file
@@ -0,0 +1,259 @@
+//! Persistent lists
+//!
+//! Q-Exprs are singly linked lists whose cells are shared between lists:
+//! `cons`, `head` and `tail` take constant time and never copy elements, so
+//! recursive list processing stays linear.
+//!
+//! Q-Exprs are also used as code, which is evaluated from a vector. The first
+//! conversion of code to a vector is remembered in its first cell, so code
+//! like the branches of `if` is only converted once. Other lists aren't
+//! converted that often, so they don't keep a copy of their elements.
+
+use std::cell::RefCell;
+use std::rc::{self, Rc};
+use lval::LVal;
+
+
+/// A persistent list
+#[derive(Clone)]
+pub struct List {
+    first: Option<Rc<Cell>>
+}
+
+struct Cell {
+    value: LVal,
+    rest: List,
+
+    /// The length of the list starting at this cell
+    len: usize,
+
+    /// The values of the list starting at this cell, once they have been
+    /// evaluated as code
+    values: RefCell<Option<Rc<Vec<LVal>>>>
+}
+
+impl List {
+    /// Create an empty list
+    pub fn new() -> List {
+        List { first: None }
+    }
+
+    pub fn from_vec(values: Vec<LVal>) -> List {
+        let mut list = List::new();
+
+        for value in values.into_iter().rev() {
+            list = list.cons(value);
+        }
+
+        list
+    }
+
+    pub fn len(&self) -> usize {
+        match self.first {
+            Some(ref cell) => cell.len,
+            None => 0
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.first.is_none()
+    }
+
+    /// Get the first element, if any
+    pub fn head(&self) -> Option<&LVal> {
+        self.first.as_ref().map(|cell| &cell.value)
+    }
+
+    /// Get the list without its first element, if it isn't empty
+    pub fn tail(&self) -> Option<List> {
+        self.first.as_ref().map(|cell| cell.rest.clone())
+    }
+
+    /// Create a list with `value` in front of this list's elements
+    pub fn cons(&self, value: LVal) -> List {
+        List {
+            first: Some(Rc::new(Cell {
+                value: value,
+                rest: self.clone(),
+                len: self.len() + 1,
+                values: RefCell::new(None)
+            }))
+        }
+    }
+
+    pub fn iter(&self) -> Iter {
+        Iter { next: self.first.as_ref().map(|cell| &**cell) }
+    }
+
+    pub fn to_vec(&self) -> Vec<LVal> {
+        self.iter().cloned().collect()
+    }
+
+    /// Get the elements as a shared vector
+    pub fn to_shared(&self) -> Rc<Vec<LVal>> {
+        Rc::new(self.to_vec())
+    }
+
+    /// Get the elements as a vector to evaluate, which is shared with later
+    /// calls
+    pub fn to_code(&self) -> Rc<Vec<LVal>> {
+        let cell = match self.first {
+            Some(ref cell) => cell,
+            None => return Rc::new(vec![])
+        };
+
+        if let Some(ref values) = *cell.values.borrow() {
+            return values.clone()
+        }
+
+        let values = Rc::new(self.to_vec());
+        *cell.values.borrow_mut() = Some(values.clone());
+
+        values
+    }
+}
+
+// Dropping a long or deeply nested list recursively could overflow the
+// stack, so the cells that aren't shared are freed one after another
+impl Drop for List {
+    fn drop(&mut self) {
+        let mut pending = vec![];
+        if let Some(cell) = self.first.take() {
+            pending.push(cell);
+        }
+
+        while let Some(cell) = pending.pop() {
+            let mut cell = match rc::try_unwrap(cell) {
+                Ok(cell) => cell,
+                Err(_) => continue
+            };
+
+            // The cached values share the elements with the cells, so they
+            // are dropped first to leave the cells as the only owners
+            drop(cell.values.borrow_mut().take());
+
+            if let Some(rest) = cell.rest.first.take() {
+                pending.push(rest);
+            }
+
+            if let LVal::QExpr(ref mut list) = cell.value {
+                if let Some(first) = list.first.take() {
+                    pending.push(first);
+                }
+            }
+        }
+    }
+}
+
+// Nested lists are compared from a worklist instead of recursively, for the
+// same reason
+impl PartialEq for List {
+    fn eq(&self, other: &List) -> bool {
+        let mut pending = vec![(self, other)];
+
+        while let Some((a, b)) = pending.pop() {
+            if a.len() != b.len() {
+                return false
+            }
+
+            for (x, y) in a.iter().zip(b.iter()) {
+                match (x, y) {
+                    (&LVal::QExpr(ref x), &LVal::QExpr(ref y)) => pending.push((x, y)),
+                    _ => if x != y { return false }
+                }
+            }
+        }
+
+        true
+    }
+}
+
+
+/// An iterator over the elements of a list
+pub struct Iter<'a> {
+    next: Option<&'a Cell>
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a LVal;
+
+    fn next(&mut self) -> Option<&'a LVal> {
+        let cell = match self.next {
+            Some(cell) => cell,
+            None => return None
+        };
+
+        self.next = cell.rest.first.as_ref().map(|cell| &**cell);
+        Some(&cell.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.next.map(|cell| cell.len).unwrap_or(0);
+        (len, Some(len))
+    }
+}
+
+
+// --- Tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use lval::LVal;
+    use super::List;
+
+    #[test]
+    fn cons_shares_cells() {
+        let list = List::from_vec(vec![LVal::int(2), LVal::int(3)]);
+        let consed = list.cons(LVal::int(1));
+
+        assert_eq!(consed.len(), 3);
+        assert_eq!(consed.head(), Some(&LVal::int(1)));
+        assert!(consed.tail().unwrap() == list);
+        assert_eq!(list.to_vec(), vec![LVal::int(2), LVal::int(3)]);
+    }
+
+    #[test]
+    fn code_vector_is_reused() {
+        let list = List::from_vec(vec![LVal::int(1)]);
+        let a = list.to_code();
+        let b = list.clone().to_code();
+
+        assert_eq!(&*a as *const Vec<LVal>, &*b as *const Vec<LVal>);
+    }
+
+    #[test]
+    fn drop_long_list() {
+        let mut list = List::new();
+        for i in 0 .. 1000000 {
+            list = list.cons(LVal::int(i));
+        }
+
+        drop(list);
+    }
+
+    #[test]
+    fn compare_nested_list() {
+        let nested = |depth: usize| {
+            let mut list = List::new();
+            for _ in 0 .. depth {
+                list = List::new().cons(LVal::QExpr(list));
+            }
+            list
+        };
+
+        assert!(nested(1000000) == nested(1000000));
+        assert!(nested(1000000) != nested(999999));
+    }
+
+    #[test]
+    fn drop_nested_code() {
+        let mut list = List::new();
+        for i in 0 .. 1000000 {
+            list = List::new().cons(LVal::QExpr(list)).cons(LVal::int(i));
+            list.to_code();
+            list.tail().unwrap().to_code();
+        }
+
+        drop(list);
+    }
+}
@@ -3,15 +3,32 @@
 //! LVal: The basic object type
 
 use std::mem;
+use std::rc::{self, Rc};
 use std::fmt;
+use std::collections::BTreeMap;
 use std::borrow::ToOwned;
+use num::{BigInt, BigRational, FromPrimitive, ToPrimitive};
 use lenv::LEnv;
+use list::List;
 use parser::ast::{Expr, ExprNode};
-use util::{print_error, stringify_vec};
+use parser::tokens::SourceLocation;
+use stack::{self, StackFrame};
+use symbol::Symbol;
+use util::{print_error, source_snippet, stringify_vec};
 
 
 /// Return an error
+///
+/// Use `err!(kind: "type-error"; ...)` to return an error of a specific kind.
 macro_rules! err(
+    (kind: $kind:expr; $msg:expr) => (
+        return LVal::err_kind($kind, $msg.to_string())
+    );
+
+    (kind: $kind:expr; $msg:expr, $( $args:expr ),* ) => (
+        return LVal::err_kind($kind, format!($msg, $($args),* ))
+    );
+
     ($msg:expr) => (
         return LVal::err($msg.to_string())
     );
@@ -23,16 +40,32 @@ macro_rules! err(
 
 
 macro_rules! lval_is(
-    ($el:expr, number) => ( if let LVal::Num(..)   = $el { true } else { false } );
+    ($el:expr, number) => ( match $el {
+        LVal::Num(..) | LVal::Int(..) | LVal::BigInt(..) | LVal::Ratio(..) => true,
+        _ => false
+    } );
+    ($el:expr, integer) => ( match $el { LVal::Int(..) | LVal::BigInt(..) => true, _ => false } );
     ($el:expr, qexpr)  => ( if let LVal::QExpr(..) = $el { true } else { false } );
     ($el:expr, sexpr)  => ( if let LVal::SExpr(..) = $el { true } else { false } );
     ($el:expr, string) => ( if let LVal::Str(..)   = $el { true } else { false } );
+    ($el:expr, bool)   => ( if let LVal::Bool(..)  = $el { true } else { false } );
+    ($el:expr, map)    => ( if let LVal::Map(..)   = $el { true } else { false } );
+    ($el:expr, err)    => ( if let LVal::Err(..)   = $el { true } else { false } );
+    ($el:expr, caught) => ( if let LVal::Caught(..) = $el { true } else { false } );
+    ($el:expr, function) => ( match $el {
+        LVal::Function{..} | LVal::Builtin(..) => true,
+        _ => false
+    } );
 );
 
 macro_rules! lval_type_name(
     (number)   => ("a number");
+    (integer)  => ("an integer");
     (err)      => ("an error");
+    (caught)   => ("a caught error");
     (string)   => ("a string");
+    (bool)     => ("a boolean");
+    (map)      => ("a map");
     (sym)      => ("a symbol");
     (function) => ("a lambda");
     (builtin)  => ("a builtin function");
@@ -43,13 +76,21 @@ macro_rules! lval_type_name(
 
 /// A builtin function
 ///
-/// Used to implement PartialEq for the function pointer
-pub struct LBuiltin(pub fn(&mut LEnv, Vec<LVal>) -> LVal);
+/// Used to implement PartialEq for the function pointer. The flags describe
+/// what the builtin does, see `builtin::flags`.
+pub struct LBuiltin(pub fn(&mut LEnv, Vec<LVal>) -> LVal, pub u32);
+
+impl LBuiltin {
+    /// Whether the builtin has all of the given flags
+    pub fn has(&self, flags: u32) -> bool {
+        self.1 & flags == flags
+    }
+}
 
 impl Clone for LBuiltin {
     fn clone(&self) -> LBuiltin {
         match *self {
-            LBuiltin(ptr) => LBuiltin(ptr),
+            LBuiltin(ptr, flags) => LBuiltin(ptr, flags),
         }
     }
 }
@@ -65,21 +106,176 @@ impl PartialEq for LBuiltin {
 }
 
 
+/// A map key
+///
+/// Only values with a total order can be used as keys.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum LKey {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    BigInt(BigInt),
+    Str(String),
+    Sym(Symbol)
+}
+
+impl LKey {
+    /// Get the key for a value or `None` if it can't be used as a key
+    pub fn from_lval(value: &LVal) -> Option<LKey> {
+        match *value {
+            LVal::Nil              => Some(LKey::Nil),
+            LVal::Bool(b)          => Some(LKey::Bool(b)),
+            LVal::Int(i)           => Some(LKey::Int(i)),
+            LVal::BigInt(ref i)    => Some(LKey::BigInt(i.clone())),
+            LVal::Str(ref s)       => Some(LKey::Str((**s).clone())),
+            LVal::Sym(s)           => Some(LKey::Sym(s)),
+            _ => None
+        }
+    }
+
+    pub fn to_lval(&self) -> LVal {
+        match *self {
+            LKey::Nil              => LVal::Nil,
+            LKey::Bool(b)          => LVal::Bool(b),
+            LKey::Int(i)           => LVal::Int(i),
+            LKey::BigInt(ref i)    => LVal::BigInt(i.clone()),
+            LKey::Str(ref s)       => LVal::str(s),
+            LKey::Sym(s)           => LVal::Sym(s)
+        }
+    }
+}
+
+impl fmt::Display for LKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_lval())
+    }
+}
+
+
+/// Where an expression has been written
+///
+/// Spans don't take part in comparisons: `(a b)` equals `(a b)` no matter
+/// where either of them comes from.
+#[derive(Clone)]
+pub struct Span(pub Option<SourceLocation>);
+
+impl Span {
+    /// A span for expressions not read from the input
+    pub fn none() -> Span {
+        Span(None)
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _: &Span) -> bool {
+        true
+    }
+}
+
+
+/// An error
+///
+/// Errors have a kind like `type-error` to tell them apart, a message, an
+/// optional payload and the location of the expression that failed, if known.
+#[derive(Clone)]
+pub struct LError {
+    pub kind: String,
+    pub message: String,
+    pub data: Option<Box<LVal>>,
+    pub location: Option<SourceLocation>,
+
+    /// The calls in progress when the error occured, innermost first
+    ///
+    /// Set by `eval` when it first sees the error.
+    pub backtrace: Option<Vec<StackFrame>>
+}
+
+impl LError {
+    pub fn new(kind: &str, message: String) -> LError {
+        LError {
+            kind: kind.to_owned(),
+            message: message,
+            data: None,
+            location: None,
+            backtrace: None
+        }
+    }
+}
+
+// Where and how an error happened doesn't change what it is
+impl PartialEq for LError {
+    fn eq(&self, other: &LError) -> bool {
+        self.kind == other.kind && self.message == other.message && self.data == other.data
+    }
+}
+
+impl fmt::Display for LError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some(ref location) => write!(f, "{} at {}", self.message, location),
+            None => write!(f, "{}", self.message)
+        }
+    }
+}
+
+
+/// Take a value out of a `Rc`, copying it if it's shared
+fn unshare<T: Clone>(shared: Rc<T>) -> T {
+    match rc::try_unwrap(shared) {
+        Ok(value) => value,
+        Err(shared) => (*shared).clone()
+    }
+}
+
+
 /// A basic object
+///
+/// Strings, lists, maps and function bodies are shared between clones, so
+/// cloning a value never copies its contents. Modifying a shared value
+/// copies it first (see `values_mut`).
 #[derive(PartialEq, Clone)]
 pub enum LVal {
     Num(f64),
-    Err(String),
-    Sym(String),  // TODO: Use SharedString?
-    Str(String),
+    Int(i64),
+    BigInt(BigInt),      // Only used if the value doesn't fit into an Int
+    Ratio(BigRational),  // Only used if the value isn't an integer
+    Bool(bool),
+    Nil,
+    Map(Rc<BTreeMap<LKey, LVal>>),
+    Err(LError),
+
+    /// An error handed to a `try` handler
+    ///
+    /// Unlike `Err` it's an ordinary value that doesn't abort the evaluation,
+    /// so the handler can pass it around and inspect it.
+    Caught(LError),
+
+    Sym(Symbol),
+
+    /// A symbol in a lambda body resolved to a local variable: the name, the
+    /// depth and the slot of the variable (see `LEnv::get_local`)
+    Local(Symbol, usize, usize),
+
+    Str(Rc<String>),
     Function {
         env: LEnv,
-        formals: Vec<LVal>,  // List of formal argument symbols
-        body:    Vec<LVal>   // Actually a S-Expr
+        formals: Rc<Vec<LVal>>,  // List of formal argument symbols
+        body:    Rc<Vec<LVal>>   // Actually a S-Expr
+    },
+    Macro {
+        env: LEnv,
+        formals: Rc<Vec<LVal>>,  // List of formal argument symbols
+        body:    Rc<Vec<LVal>>   // Actually a S-Expr
     },
     Builtin(LBuiltin),
-    SExpr(Vec<LVal>),
-    QExpr(Vec<LVal>)
+    SExpr(Rc<Vec<LVal>>, Span),
+    QExpr(List),
+
+    /// A S-Expr the evaluator continues with in the current environment
+    ///
+    /// Returned by builtins like `if` so their result is evaluated in tail
+    /// position. Never escapes `eval`.
+    TailCall(Rc<Vec<LVal>>)
 }
 
 impl LVal {
@@ -91,53 +287,136 @@ impl LVal {
         LVal::Num(value)
     }
 
-    /// Create a new error lval
+    /// Create a new integer lval
+    pub fn int(value: i64) -> LVal {
+        LVal::Int(value)
+    }
+
+    /// Create a new integer lval from a big integer
+    ///
+    /// Uses `LVal::Int` if possible.
+    pub fn bigint(value: BigInt) -> LVal {
+        match value.to_i64() {
+            Some(int) => LVal::Int(int),
+            None => LVal::BigInt(value)
+        }
+    }
+
+    /// Create a new rational lval
+    ///
+    /// Uses an integer lval if the denominator is 1.
+    pub fn ratio(value: BigRational) -> LVal {
+        if value.is_integer() {
+            LVal::bigint(value.to_integer())
+        } else {
+            LVal::Ratio(value)
+        }
+    }
+
+    /// Create a new boolean lval
+    pub fn bool(value: bool) -> LVal {
+        LVal::Bool(value)
+    }
+
+    /// Create a new nil lval
+    pub fn nil() -> LVal {
+        LVal::Nil
+    }
+
+    /// Create a new error lval of the generic kind `error`
     pub fn err(msg: String) -> LVal {
-        LVal::Err(msg)
+        LVal::err_kind("error", msg)
+    }
+
+    /// Create a new error lval of the given kind
+    pub fn err_kind(kind: &str, msg: String) -> LVal {
+        LVal::Err(LError::new(kind, msg))
     }
 
     /// Create a new string lval
     pub fn str(s: &str) -> LVal {
-        LVal::Str(s.to_owned())
+        LVal::Str(Rc::new(s.to_owned()))
+    }
+
+    /// Create a new string lval from an owned string
+    pub fn string(s: String) -> LVal {
+        LVal::Str(Rc::new(s))
     }
 
     /// Create a new symbol lval
     pub fn sym(symbol: &str) -> LVal {
-        LVal::Sym(symbol.to_owned())
+        LVal::Sym(Symbol::intern(symbol))
     }
 
-    // Create a new lambda lval
-    pub fn lambda(formals: LVal, body: LVal) -> LVal {
+    // Create a new lambda lval closing over `env`
+    pub fn lambda(env: &LEnv, formals: LVal, body: LVal) -> LVal {
         LVal::Function {
-            env:     LEnv::new(),
-            formals: formals.into_values(),
-            body:    body.into_values()
+            env:     LEnv::with_parent(env),
+            formals: formals.into_shared_values(),
+            body:    body.into_shared_values()
+        }
+    }
+
+    // Create a new macro lval closing over `env`
+    pub fn mac(env: &LEnv, formals: LVal, body: LVal) -> LVal {
+        LVal::Macro {
+            env:     LEnv::with_parent(env),
+            formals: formals.into_shared_values(),
+            body:    body.into_shared_values()
         }
     }
 
     /// Create a new function lval
     pub fn func(f: fn(&mut LEnv, Vec<LVal>) -> LVal) -> LVal {
-        LVal::Builtin(LBuiltin(f))
+        LVal::func_with(f, 0)
+    }
+
+    /// Create a new function lval with the given `builtin::flags`
+    pub fn func_with(f: fn(&mut LEnv, Vec<LVal>) -> LVal, flags: u32) -> LVal {
+        LVal::Builtin(LBuiltin(f, flags))
     }
 
     /// Create a new sepxr lval
     pub fn sexpr() -> LVal {
-        LVal::SExpr(vec![])
+        LVal::sexpr_of(vec![])
+    }
+
+    /// Create a new sexpr lval containing `values`
+    pub fn sexpr_of(values: Vec<LVal>) -> LVal {
+        LVal::SExpr(Rc::new(values), Span::none())
     }
 
     /// Create a new sepxr lval
     pub fn qexpr() -> LVal {
-        LVal::QExpr(vec![])
+        LVal::qexpr_of(vec![])
+    }
+
+    /// Create a new qexpr lval containing `values`
+    pub fn qexpr_of(values: Vec<LVal>) -> LVal {
+        LVal::QExpr(List::from_vec(values))
+    }
+
+    /// Create a new map lval
+    pub fn map(map: BTreeMap<LKey, LVal>) -> LVal {
+        LVal::Map(Rc::new(map))
     }
 
     /// Construct a lval from a given AST
+    ///
+    /// S-Exprs keep their location, so errors can point back to the input.
     pub fn from_ast(ast: ExprNode) -> LVal {
-        match ast.value {
-            Expr::Number(i) => LVal::num(i as f64),
+        let ExprNode { value, location } = ast;
+
+        match value {
+            Expr::Number(f) => LVal::num(f),
+            Expr::Integer(i) => LVal::int(i),
+            Expr::Rational(r) => LVal::ratio(r),
+            Expr::Bool(b) => LVal::bool(b),
+            Expr::Nil => LVal::nil(),
             Expr::String(s) => LVal::str(&s),
             Expr::Symbol(s) => LVal::sym(&s),
             Expr::SExpr(exprs) => {
-                let mut sexpr = LVal::sexpr();
+                let mut sexpr = LVal::SExpr(Rc::new(vec![]), Span(Some(location)));
                 for child in exprs {
                     sexpr.append(LVal::from_ast(child));
                 }
@@ -145,66 +424,155 @@ impl LVal {
                 sexpr
             },
             Expr::QExpr(exprs) => {
-                let mut qexpr = LVal::qexpr();
-                for child in exprs {
-                    qexpr.append(LVal::from_ast(child));
-                }
-
-                qexpr
+                LVal::qexpr_of(exprs.into_iter().map(LVal::from_ast).collect())
             }
         }
     }
 
     // --- Public methods: Conversions ------------------------------------------
 
-    pub fn as_values(&self) -> &Vec<LVal> {
-        if let &LVal::SExpr(ref values) = self {
-            values
-        } else if let &LVal::QExpr(ref values) = self {
-            values
-        } else {
-            panic!("LVal::as_values(self={})", self);
+    /// Get the values of an expression
+    ///
+    /// Converting a Q-Expr takes linear time.
+    pub fn values(&self) -> Rc<Vec<LVal>> {
+        match *self {
+            LVal::SExpr(ref values, _) => values.clone(),
+            LVal::QExpr(ref list) => list.to_shared(),
+            _ => panic!("LVal::values(self={})", self)
         }
     }
 
+    pub fn as_list(&self) -> &List {
+        match *self {
+            LVal::QExpr(ref list) => list,
+            _ => panic!("LVal::as_list(self={})", self)
+        }
+    }
+
+    /// Take the values of an expression, copying them if they're shared
     pub fn into_values(self) -> Vec<LVal> {
-        if let LVal::SExpr(values) = self {
-            values
-        } else if let LVal::QExpr(values) = self {
-            values
+        match self {
+            LVal::QExpr(list) => list.to_vec(),
+            other => unshare(other.into_shared_values())
+        }
+    }
+
+    pub fn into_shared_values(self) -> Rc<Vec<LVal>> {
+        match self {
+            LVal::SExpr(values, _) => values,
+            LVal::QExpr(list) => list.to_shared(),
+            other => panic!("LVal::into_shared_values(self={})", other)
+        }
+    }
+
+    /// Get the values of an expression to evaluate
+    ///
+    /// Converting a Q-Expr takes linear time the first time, later calls
+    /// share the result (see `List::to_code`).
+    pub fn into_code(self) -> Rc<Vec<LVal>> {
+        match self {
+            LVal::QExpr(list) => list.to_code(),
+            other => other.into_shared_values()
+        }
+    }
+
+    /// Get the values of a S-Expr for modification
+    ///
+    /// Copies the values first if they're shared with another lval.
+    pub fn values_mut(&mut self) -> &mut Vec<LVal> {
+        match *self {
+            LVal::SExpr(ref mut values, _) => values.make_unique(),
+            ref other => panic!("LVal::values_mut(self={})", other)
+        }
+    }
+
+    /// Get the value of a number as a float
+    pub fn as_num(&self) -> f64 {
+        fn bigint_to_f64(int: &BigInt) -> f64 {
+            format!("{}", int).parse().unwrap()
+        }
+
+        match *self {
+            LVal::Num(float) => float,
+            LVal::Int(int) => int as f64,
+            LVal::BigInt(ref int) => bigint_to_f64(int),
+            LVal::Ratio(ref ratio) => {
+                bigint_to_f64(ratio.numer()) / bigint_to_f64(ratio.denom())
+            },
+            _ => panic!("LVal::as_num(self={})", self)
+        }
+    }
+
+    pub fn into_num(self) -> f64 {
+        self.as_num()
+    }
+
+    pub fn as_bigint(&self) -> BigInt {
+        match *self {
+            LVal::Int(int) => BigInt::from_i64(int).unwrap(),
+            LVal::BigInt(ref int) => int.clone(),
+            _ => panic!("LVal::as_bigint(self={})", self)
+        }
+    }
+
+    /// Get the value of an exact number as a rational
+    pub fn as_ratio(&self) -> BigRational {
+        match *self {
+            LVal::Int(..) | LVal::BigInt(..) => BigRational::from_integer(self.as_bigint()),
+            LVal::Ratio(ref ratio) => ratio.clone(),
+            _ => panic!("LVal::as_ratio(self={})", self)
+        }
+    }
+
+    /// Whether this is an exact number (i.e. not a float)
+    pub fn is_exact(&self) -> bool {
+        match *self {
+            LVal::Int(..) | LVal::BigInt(..) | LVal::Ratio(..) => true,
+            _ => false
+        }
+    }
+
+    pub fn as_map(&self) -> &BTreeMap<LKey, LVal> {
+        if let LVal::Map(ref map) = *self {
+            return map
         } else {
-            panic!("LVal::into_values(self={})", self);
+            panic!("LVal::as_map(self={})", self)
         }
     }
 
-    pub fn as_num(&self) -> &f64 {
-        if let &LVal::Num(ref float) = self {
-            return float
+    pub fn into_map(self) -> BTreeMap<LKey, LVal> {
+        if let LVal::Map(map) = self {
+            return unshare(map)
         } else {
-            panic!("LVal::as_num(self={})", self)
+            panic!("LVal::into_map(self={})", self)
         }
     }
 
-    pub fn into_num(self) -> f64 {
-        if let LVal::Num(float) = self {
-            //let Float(ref mut i) = float;
-            //return i
-            return float
+    pub fn as_str(&self) -> &str {
+        if let LVal::Str(ref s) = *self {
+            return s
         } else {
-            panic!("LVal::into_num(self={})", self)
+            panic!("LVal::as_str(self={})", self)
         }
     }
 
     pub fn into_str(self) -> String {
         if let LVal::Str(s) = self {
-            return s
+            return unshare(s)
         } else {
             panic!("LVal::into_str(self={})", self)
         }
     }
 
-    pub fn as_sym(&self) -> &String {
-        if let &LVal::Sym(ref value) = self {
+    pub fn as_err(&self) -> &LError {
+        match *self {
+            LVal::Err(ref err) | LVal::Caught(ref err) => err,
+            _ => panic!("LVal::as_err(self={})", self)
+        }
+    }
+
+    pub fn as_sym(&self) -> Symbol {
+        if let &LVal::Sym(value) = self {
             return value
         } else {
             panic!("LVal::as_sym(self={})", self)
@@ -213,6 +581,23 @@ impl LVal {
 
     // --- Public methods: Other functions --------------------------------------
 
+    /// Whether this value counts as true in a condition
+    ///
+    /// `false`, `nil`, zero and empty expressions are false, everything else
+    /// is true.
+    pub fn is_truthy(&self) -> bool {
+        match *self {
+            LVal::Bool(b) => b,
+            LVal::Nil => false,
+            LVal::Num(..) | LVal::Int(..) |
+            LVal::BigInt(..) | LVal::Ratio(..) => self.as_num() != 0.,
+            LVal::SExpr(ref values, _) => values.len() > 0,
+            LVal::QExpr(ref list) => !list.is_empty(),
+            LVal::Map(ref map) => map.len() > 0,
+            _ => true
+        }
+    }
+
     /// Delete a lval
     pub fn del(self) {}
 
@@ -220,50 +605,48 @@ impl LVal {
     ///
     /// Panics when `self` is not a SExpr
     pub fn append(&mut self, expr: LVal) {
-        if let LVal::SExpr(ref mut values) = *self {
-            values.push(expr);
-        } else if let LVal::QExpr(ref mut values) = *self {
-            values.push(expr);
-        } else {
-            panic!("LVal::append(self={}, expr={})", self, expr);
-        }
+        self.values_mut().push(expr);
     }
 
-    /// Append all values from a sexpr to a sexpr
+    /// Append all values from a sexpr or qexpr to a sexpr
     ///
-    /// Panics when `self` or `container` is not a SExpr
+    /// Panics when `self` is not a SExpr
     pub fn extend(&mut self, container: LVal) {
-        if let LVal::SExpr(ref mut values) = *self {
-            values.extend(container.into_values().into_iter());
-        } else if let LVal::QExpr(ref mut values) = *self {
-            values.extend(container.into_values().into_iter());
-        } else {
-            panic!("LVal::extend(self={}, expr={})", self, container);
-        }
+        self.values_mut().extend(container.into_values().into_iter());
     }
 
     pub fn type_name(&self) -> &'static str {
         match *self {
             LVal::Num(..)      => "a number",
+            LVal::Int(..)      => "an integer",
+            LVal::BigInt(..)   => "an integer",
+            LVal::Ratio(..)    => "a rational",
+            LVal::Bool(..)     => "a boolean",
+            LVal::Nil          => "nil",
+            LVal::Map(..)      => "a map",
             LVal::Err(..)      => "an error",
+            LVal::Caught(..)   => "a caught error",
             LVal::Sym(..)      => "a symbol",
+            LVal::Local(..)    => "a symbol",
             LVal::Str(..)      => "a string",
             LVal::Function{..} => "a lambda",
+            LVal::Macro{..}    => "a macro",
             LVal::Builtin(..)  => "a builtin function",
             LVal::SExpr(..)    => "a s-expression",
-            LVal::QExpr(..)    => "a q-expression"
+            LVal::QExpr(..)    => "a q-expression",
+            LVal::TailCall(..) => "a tail call"
         }
     }
 
     pub fn to_string(&self, env: &LEnv) -> String {
         match *self {
-            LVal::Sym(ref name) => {
-                match env.get(&name) {
-                    LVal::Err(..) => name.clone(),
+            LVal::Sym(name) | LVal::Local(name, _, _) => {
+                match env.get(name) {
+                    LVal::Err(..) => name.to_string(),
                     value         => value.to_string(env)
                 }
             },
-            LVal::SExpr(ref values) => {
+            LVal::SExpr(ref values, _) => {
                 format!(
                     "({})",
                     values.iter()
@@ -273,11 +656,26 @@ impl LVal {
                 )
             }
             LVal::Function { ref env, ref formals, ref body } => {
+                // Only show the arguments which have already been bound
+                let env = env.locals();
+
                 format!(
                     "\\ {{{}}} {{{}}}",
                     stringify_vec(formals),
                     body.iter()
-                        .map(|v| v.to_string(env))
+                        .map(|v| v.to_string(&env))
+                        .collect::<Vec<_>>()
+                        .connect(" ")
+                )
+            },
+            LVal::Macro { ref env, ref formals, ref body } => {
+                let env = env.locals();
+
+                format!(
+                    "macro {{{}}} {{{}}}",
+                    stringify_vec(formals),
+                    body.iter()
+                        .map(|v| v.to_string(&env))
                         .collect::<Vec<_>>()
                         .connect(" ")
                 )
@@ -291,7 +689,20 @@ impl LVal {
     }
 
     pub fn print(&self, env: &LEnv) {
-        if let LVal::Err(ref msg) = *self {
+        if let LVal::Err(ref err) = *self {
+            let mut msg = err.message.clone();
+
+            if let Some(ref location) = err.location {
+                msg.push_str("\n");
+                msg.push_str(&source_snippet(location));
+            }
+
+            if let Some(ref backtrace) = err.backtrace {
+                for frame in backtrace {
+                    msg.push_str(&format!("\n    in {}", frame));
+                }
+            }
+
             print_error(&msg);
         } else {
             print!("{}", self.to_string(env));
@@ -309,20 +720,43 @@ impl LVal {
 impl fmt::Display for LVal {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            LVal::Num(i)            => write!(f, "{}", i),
-            LVal::Err(ref msg)      => write!(f, "{}", msg),
+            LVal::Num(n)            => write!(f, "{}", n),
+            LVal::Int(i)            => write!(f, "{}", i),
+            LVal::BigInt(ref i)     => write!(f, "{}", i),
+            LVal::Ratio(ref r)      => write!(f, "{}", r),
+            LVal::Bool(b)           => write!(f, "{}", b),
+            LVal::Nil               => write!(f, "nil"),
+            LVal::Map(ref map) => {
+                write!(f, "#{{{}}}", map.iter()
+                    .map(|(key, value)| format!("{} {}", key, value))
+                    .collect::<Vec<_>>()
+                    .connect(" "))
+            },
+            LVal::Err(ref err)      => write!(f, "{}", err.message),
+            LVal::Caught(ref err)   => write!(f, "<{}: {}>", err.kind, err.message),
             LVal::Str(ref string)   => write!(f, "\"{}\"", string.escape_default()),
             LVal::Sym(ref symbol)   => write!(f, "{}", symbol),
+            LVal::Local(ref symbol, _, _) => write!(f, "{}", symbol),
             LVal::Function{ env: _, ref formals, ref body } => {
                 write!(f, "\\ {{{}}} {{{}}}", stringify_vec(formals),
                                               stringify_vec(body))
             },
+            LVal::Macro{ env: _, ref formals, ref body } => {
+                write!(f, "macro {{{}}} {{{}}}", stringify_vec(formals),
+                                                 stringify_vec(body))
+            },
             LVal::Builtin(..)       => write!(f, "<function>"),
-            LVal::SExpr(ref values) => {
-                write!(f, "({})", stringify_vec(values))
+            // Values nested too deeply are left out
+            LVal::SExpr(ref values, _) => match stack::nested(|| stringify_vec(values)) {
+                Some(values) => write!(f, "({})", values),
+                None => write!(f, "(...)")
+            },
+            LVal::QExpr(ref list) => match stack::nested(|| stringify_vec(&list.to_vec())) {
+                Some(values) => write!(f, "{{{}}}", values),
+                None => write!(f, "{{...}}")
             },
-            LVal::QExpr(ref values) => {
-                write!(f, "{{{}}}", stringify_vec(values))
+            LVal::TailCall(ref values) => {
+                write!(f, "<tail call: ({})>", stringify_vec(values))
             }
         }
     }
@@ -1,4 +1,5 @@
 #![feature(collections)]
+#![feature(alloc)]
 #![feature(core)]
 #![feature(fs)]
 #![feature(io)]
@@ -13,31 +14,43 @@
 #[macro_use] extern crate log;
 
 extern crate ansi_term;
+extern crate num;
 extern crate "readline-sys" as readline;
 
 mod parser;
 mod lval;
 mod lenv;
+mod list;
 mod eval;
 mod builtin;
+mod stack;
+mod fuel;
+mod symbol;
+mod resolve;
+mod optimize;
+mod vm;
+mod options;
 mod util;
 
+#[cfg(test)]
+mod testing;
+
 
 
 #[cfg(not(test))]
 mod main {
     use readline;
 
-    use util::print_error;
-    use eval::eval;
+    use util::print_error_at;
+    use options::Options;
     use lval::LVal;
     use lenv::LEnv;
     use parser::Parser;
     use builtin;
 
-    pub fn repl() {
+    pub fn repl(options: &Options) {
         let mut env = LEnv::new();
-        builtin::initialize(&mut env);
+        builtin::initialize(&mut env, options.prelude);
 
         println!("MLisp Version 0.0.0.1");
         println!("Enter 'quit' to exit");
@@ -73,15 +86,19 @@ mod main {
             // Parsing
             let ast = match Parser::parse(&input, "<input>") {
                 Ok(lval) => lval,
-                Err(err) => { print_error(&format!("{:?}\n", err)); continue }
+                Err(err) => {
+                    print_error_at(&err.message(), err.location());
+                    println!("");
+                    continue
+                }
             };
             let lval = LVal::from_ast(ast);
 
             // Evaluating
-            let result = eval(&mut env, lval);
+            let result = options.evaluate(&mut env, lval);
 
             // Printing
-            if let LVal::SExpr(ref v) = result {
+            if let LVal::SExpr(ref v, _) = result {
                 if v.len() == 0 {
                     continue
                 }
@@ -93,12 +110,13 @@ mod main {
         println!("Exiting...")
     }
 
-    pub fn run_files(args: Vec<String>) {
-        for file in args.into_iter().skip(1) {
+    pub fn run_files(options: &Options) {
+        for file in &options.files {
             let mut env = LEnv::new();
-            builtin::initialize(&mut env);
+            builtin::initialize(&mut env, options.prelude);
 
-            let result = builtin::env::builtin_load(&mut env, vec![LVal::str(&file)]);
+            let result = builtin::env::load_file(&mut env, file,
+                                                 &|env: &mut LEnv, node: LVal| options.evaluate(env, node));
             if let LVal::Err(..) = result {
                 result.println(&env);
             }
@@ -109,12 +127,38 @@ mod main {
 #[cfg(not(test))]
 fn main() {
     use std::env;
+    use std::thread;
+    use options::Options;
+
+    let options = match Options::parse(env::args().collect()) {
+        Ok(options) => options,
+        Err(msg) => {
+            util::print_error(&msg);
+            println!("");
+            return
+        }
+    };
+
+    // The evaluator recurses natively, so it gets a stack large enough for
+    // the depth limit
+    let evaluator = thread::Builder::new()
+        .name("evaluator".to_string())
+        .stack_size(stack::stack_size(options.max_depth))
+        .spawn(move || {
+            stack::set_max_depth(options.max_depth);
+
+            if options.files.len() > 0 {
+                main::run_files(&options)
+            } else {
+                main::repl(&options)
+            }
+        });
 
-    let args: Vec<_> = env::args().collect();
-
-    if args.len() >= 2 {
-        main::run_files(args)
-    } else {
-        main::repl()
+    match evaluator {
+        Ok(evaluator) => { let _ = evaluator.join(); },
+        Err(err) => {
+            util::print_error(&format!("cannot start the evaluator: {}", err));
+            println!("");
+        }
     }
 }
\ No newline at end of file
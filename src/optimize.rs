@@ -0,0 +1,248 @@
+//! The optimizer
+//!
+//! An optional pass over code before it's evaluated. Calls of pure builtins
+//! (see `builtin::flags::PURE`) whose arguments are constants are replaced by
+//! their result, and `if`s with a constant condition are replaced by the
+//! branch that would be taken.
+//!
+//! Builtins are looked up when optimizing, so code that may rebind one of
+//! the folded builtins is left alone. Lambda bodies aren't optimized, as the
+//! builtins could be rebound before they run. Calls that fail are left alone
+//! to report the error when they are evaluated, and so is code nested deeper
+//! than the depth limit (see `stack::nested`).
+
+use std::cmp;
+use std::rc::Rc;
+use lval::{LVal, Span};
+use lenv::LEnv;
+use eval::call_builtin;
+use builtin::flags::{PURE, EVALUATES_BRANCHES, BINDS_NAMES, WRITES_ENV};
+use stack;
+
+
+/// Optimize code to be evaluated in `env`
+pub fn optimize(env: &LEnv, node: LVal) -> LVal {
+    let rebinds = match node {
+        LVal::SExpr(ref values, _) => may_rebind(env, values),
+        _ => false
+    };
+
+    if rebinds {
+        return node
+    }
+
+    optimize_expr(env, node)
+}
+
+
+fn optimize_expr(env: &LEnv, node: LVal) -> LVal {
+    match node {
+        LVal::SExpr(values, span) => {
+            let optimized = stack::nested(|| optimize_sexpr(env, &values, span.clone()));
+
+            match optimized {
+                Some(optimized) => optimized,
+                None => LVal::SExpr(values, span)
+            }
+        },
+        node => node
+    }
+}
+
+
+/// Whether a value names a builtin the optimizer folds
+fn is_folded(env: &LEnv, value: &LVal) -> bool {
+    match *value {
+        LVal::Sym(name) => match env.get(name) {
+            LVal::Builtin(ref builtin) => builtin.has(PURE) || builtin.has(EVALUATES_BRANCHES),
+            _ => false
+        },
+        _ => false
+    }
+}
+
+
+/// Whether code may rebind a builtin the optimizer folds
+///
+/// Names are bound from the first Q-Expr passed to `def`, `=` and `\`, and
+/// from any Q-Expr passed to macros like `fun`, while `eval` and `load` run
+/// code that isn't known in advance. Q-Exprs are searched too, as they may be
+/// lambda bodies.
+fn may_rebind(env: &LEnv, values: &Vec<LVal>) -> bool {
+    let names = match values.get(0) {
+        Some(&LVal::Sym(name)) => match env.get(name) {
+            LVal::Macro { .. } => values.len(),
+            LVal::Builtin(ref builtin) => {
+                if builtin.has(WRITES_ENV) && !builtin.has(BINDS_NAMES) {
+                    return true
+                }
+
+                if builtin.has(BINDS_NAMES) { cmp::min(values.len(), 2) } else { 1 }
+            },
+            _ => 1
+        },
+        Some(..) => 1,
+        None => return false
+    };
+
+    let rebinds = values[1..names].iter().any(|value| match *value {
+        LVal::QExpr(ref names) => names.iter().any(|name| is_folded(env, name)),
+        _ => false
+    });
+
+    rebinds || values.iter().any(|value| match *value {
+        LVal::SExpr(ref values, _) => stack::nested(|| may_rebind(env, values)).unwrap_or(true),
+        LVal::QExpr(ref list) => stack::nested(|| may_rebind(env, &list.to_shared())).unwrap_or(true),
+        _ => false
+    })
+}
+
+
+/// Whether a value evaluates to itself and can be passed to pure builtins
+fn is_constant(value: &LVal) -> bool {
+    match *value {
+        LVal::Int(..) | LVal::BigInt(..) | LVal::Ratio(..) | LVal::Num(..) |
+        LVal::Bool(..) | LVal::Nil | LVal::Str(..) => true,
+        _ => false
+    }
+}
+
+
+fn optimize_sexpr(env: &LEnv, values: &Vec<LVal>, span: Span) -> LVal {
+    let head = match values.get(0) {
+        // Local variables may be assigned another value later
+        Some(&LVal::Sym(name)) if env.address(name).is_none() => env.get(name),
+        _ => LVal::Nil
+    };
+
+    let builtin = match head {
+        // Macros get their arguments as they are written
+        LVal::Macro { .. } => return LVal::SExpr(Rc::new(values.clone()), span),
+        LVal::Builtin(builtin) => Some(builtin),
+        _ => None
+    };
+
+    let branches = builtin.as_ref().map(|builtin| builtin.has(EVALUATES_BRANCHES)).unwrap_or(false);
+
+    let mut values: Vec<LVal> = values.iter().enumerate().map(|(i, value)| match *value {
+        LVal::QExpr(ref branch) if branches && i > 1 => {
+            LVal::qexpr_of(optimize_branch(env, &branch.to_shared()).into_values())
+        },
+        ref value => optimize_expr(env, value.clone())
+    }).collect();
+
+    // Eliminate the branch that isn't taken
+    if branches && values.len() >= 3 && values.len() <= 4 && is_constant(&values[1]) &&
+            values[2..].iter().all(|value| lval_is!(*value, qexpr)) {
+        let branch = if values[1].is_truthy() {
+            values.swap_remove(2)
+        } else if values.len() == 4 {
+            values.swap_remove(3)
+        } else {
+            return LVal::sexpr()
+        };
+
+        // A single constant is the value of the branch
+        let mut branch = branch.into_values();
+        if branch.len() == 1 && is_constant(&branch[0]) {
+            return branch.remove(0)
+        }
+
+        return LVal::SExpr(Rc::new(branch), span)
+    }
+
+    // Fold pure calls on constants
+    if let Some(ref builtin) = builtin {
+        if builtin.has(PURE) && values[1..].iter().all(is_constant) {
+            let args = values[1..].to_vec();
+
+            match call_builtin(builtin.0, &mut env.clone(), args) {
+                LVal::Err(..) => {},
+                value => return value
+            }
+        }
+    }
+
+    // A S-Expr with a single constant evaluates to it
+    if values.len() == 1 && is_constant(&values[0]) {
+        return values.remove(0)
+    }
+
+    LVal::SExpr(Rc::new(values), span)
+}
+
+
+/// Optimize the contents of an `if` branch, which are evaluated as a S-Expr
+fn optimize_branch(env: &LEnv, branch: &Vec<LVal>) -> LVal {
+    let optimized = stack::nested(|| optimize_sexpr(env, branch, Span::none()));
+
+    match optimized {
+        Some(sexpr @ LVal::SExpr(..)) => sexpr,
+        None => LVal::sexpr_of(branch.clone()),
+
+        // Keep it a list of values to evaluate
+        Some(value) => LVal::sexpr_of(vec![value])
+    }
+}
+
+
+// --- Tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use eval::eval;
+    use lval::LVal;
+    use lenv::LEnv;
+    use testing::{env, parse, run_in};
+    use super::optimize;
+
+    fn optimized(code: &str) -> String {
+        let node = parse(code).into_values().remove(0);
+        format!("{}", optimize(&env(true), node))
+    }
+
+    #[test]
+    fn fold_constants() {
+        assert_eq!(optimized("(* x (+ 1 2))"), "(* x 3)");
+        assert_eq!(optimized("(list (< 1 2) (not true) (/ 1 2))"), "(list true false 1/2)");
+    }
+
+    #[test]
+    fn eliminate_branches() {
+        assert_eq!(optimized("(if (== 1 1) {f (+ 1 1)} {g})"), "(f 2)");
+        assert_eq!(optimized("(if 0 {f} {+ 2 3})"), "5");
+        assert_eq!(optimized("(if x {- 3 1} {y})"), "(if x {2} {y})");
+    }
+
+    #[test]
+    fn keep_errors_and_data() {
+        assert_eq!(optimized("(/ 1 0)"), "(/ 1 0)");
+        assert_eq!(optimized("(head {(+ 1 2)})"), "(head {(+ 1 2)})");
+    }
+
+    fn run_optimized(lines: &[&str]) -> LVal {
+        run_in(&mut env(true), lines, &|env: &mut LEnv, node: LVal| {
+            let node = optimize(env, node);
+            eval(env, node)
+        })
+    }
+
+    #[test]
+    fn keep_lambda_bodies() {
+        assert_eq!(format!("{}", run_optimized(&["(\\ {x} {* x (+ 1 2)})"])), "\\ {x} {* x (+ 1 2)}");
+
+        // The lambda must see `+` rebound after it's created
+        assert_eq!(
+            run_optimized(&["(fun {f x} {+ 1 2})", "(def {+} -)", "(f 0)"]),
+            LVal::int(-1)
+        );
+    }
+
+    #[test]
+    fn keep_rebound_builtins() {
+        assert_eq!(run_optimized(&["(do (def {+} -) (+ 1 2))"]), LVal::int(-1));
+        assert_eq!(optimized("(do (fun {if c a b} {b}) (if true {1} {2}))"),
+                   "(do (fun {if c a b} {b}) (if true {1} {2}))");
+        assert_eq!(run_optimized(&["(def {f} (\\ {} {do (= {*} +) (* 2 3)}))", "(f)"]), LVal::int(5));
+    }
+}
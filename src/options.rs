@@ -0,0 +1,156 @@
+//! Command line options
+
+use eval::eval;
+use vm;
+use optimize::optimize;
+use lval::LVal;
+use lenv::LEnv;
+use stack;
+use fuel;
+
+
+/// Command line options
+pub struct Options {
+    /// Evaluate the standard prelude on startup
+    pub prelude: bool,
+
+    /// Run code on the virtual machine instead of the evaluator
+    pub vm: bool,
+
+    /// Optimize code before running it
+    pub optimize: bool,
+
+    /// Print the optimized code before running it
+    pub dump_optimized: bool,
+
+    /// How deeply evaluations may nest, see `stack::set_max_depth`
+    pub max_depth: usize,
+
+    /// The number of steps each top-level expression may take, see
+    /// `fuel::eval_with_budget`
+    pub fuel: Option<u64>,
+
+    /// The files to run, the REPL is started if there are none
+    pub files: Vec<String>
+}
+
+impl Options {
+    pub fn parse(args: Vec<String>) -> Result<Options, String> {
+        let mut options = Options {
+            prelude: true,
+            vm: false,
+            optimize: false,
+            dump_optimized: false,
+            max_depth: stack::DEFAULT_MAX_DEPTH,
+            fuel: None,
+            files: vec![]
+        };
+
+        let mut args = args.into_iter().skip(1);
+
+        while let Some(arg) = args.next() {
+            match &*arg {
+                "--no-prelude" => options.prelude = false,
+                "--vm" => options.vm = true,
+                "--optimize" => options.optimize = true,
+                "--dump-optimized" => {
+                    options.optimize = true;
+                    options.dump_optimized = true;
+                },
+                "--max-depth" => {
+                    options.max_depth = match args.next().and_then(|depth| depth.parse::<usize>().ok()) {
+                        Some(depth) => depth,
+                        None => return Err("--max-depth expects a number".to_string())
+                    };
+                },
+                "--fuel" => {
+                    options.fuel = match args.next().and_then(|steps| steps.parse::<u64>().ok()) {
+                        Some(steps) => Some(steps),
+                        None => return Err("--fuel expects a number".to_string())
+                    };
+                },
+                _ if arg.starts_with("--") => {
+                    return Err(format!("unknown option: {}", arg))
+                },
+                _ => options.files.push(arg.clone())
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// The function to evaluate code with
+    pub fn evaluator(&self) -> fn(&mut LEnv, LVal) -> LVal {
+        if self.vm {
+            return vm::run
+        }
+
+        eval
+    }
+
+    /// Evaluate code as selected by the options
+    pub fn evaluate(&self, env: &mut LEnv, node: LVal) -> LVal {
+        // Folding calls builtins, so the optimizer runs on the budget too
+        fuel::eval_with_budget(env, node, self.fuel, &|env: &mut LEnv, node: LVal| {
+            let node = if self.optimize { optimize(env, node) } else { node };
+
+            if self.dump_optimized {
+                println!("{}", node);
+            }
+
+            (self.evaluator())(env, node)
+        })
+    }
+}
+
+
+// --- Tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use lval::LVal;
+    use lenv::LEnv;
+    use fuel::out_of_fuel;
+    use testing::{env, run_in};
+    use super::Options;
+
+    fn options(args: &[&str]) -> Result<Options, String> {
+        let mut args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        args.insert(0, "lispy".to_string());
+
+        Options::parse(args)
+    }
+
+    fn run(args: &[&str], lines: &[&str]) -> LVal {
+        let options = options(args).ok().expect("invalid options");
+        run_in(&mut env(false), lines, &|env: &mut LEnv, node: LVal| options.evaluate(env, node))
+    }
+
+    #[test]
+    fn fuel_stops_loop() {
+        let lines = ["(def {forever} (\\ {n} {forever (+ n 1)}))", "(forever 0)"];
+
+        assert_eq!(run(&["--fuel", "10000"], &lines), out_of_fuel());
+        assert_eq!(run(&["--fuel", "10000", "--vm"], &lines), out_of_fuel());
+    }
+
+    #[test]
+    fn fuel_per_expression() {
+        // Two S-Exprs and two builtin calls, plus the line itself
+        assert_eq!(run(&["--fuel", "5"], &["(+ 1 (* 2 3))", "(+ 1 (* 2 3))"]), LVal::int(7));
+        assert_eq!(run(&["--fuel", "4"], &["(+ 1 (* 2 3))"]), out_of_fuel());
+    }
+
+    #[test]
+    fn fuel_covers_optimizer() {
+        // Folding `+` takes the only step, the constant is free
+        assert_eq!(run(&["--optimize", "--fuel", "1"], &["(+ 1 2)"]), LVal::int(3));
+        assert_eq!(run(&["--optimize", "--fuel", "0"], &["(+ 1 2)"]), out_of_fuel());
+    }
+
+    #[test]
+    fn fuel_expects_number() {
+        assert!(options(&["--fuel", "lots"]).is_err());
+        assert!(options(&["--fuel"]).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 //! Abstract syntax tree
 
 use std::fmt;
+use num::BigRational;
 use parser::tokens::SourceLocation;
 use parser::util::SharedString;
 use util::stringify_vec;
@@ -50,13 +51,29 @@ pub enum Expr {
     Symbol(SharedString),
 
     /// A number
-    Number(f64)
+    Number(f64),
+
+    /// An integer
+    Integer(i64),
+
+    /// An exact number that doesn't fit into an integer
+    Rational(BigRational),
+
+    /// A boolean
+    Bool(bool),
+
+    /// Nil
+    Nil
 }
 
 impl fmt::Debug for Expr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Expr::Number(i)          => write!(f, "{}", i),
+            Expr::Number(n)          => write!(f, "{}", n),
+            Expr::Integer(i)         => write!(f, "{}", i),
+            Expr::Rational(ref r)    => write!(f, "{}", r),
+            Expr::Bool(b)            => write!(f, "{}", b),
+            Expr::Nil                => write!(f, "nil"),
             Expr::String(ref string) => write!(f, "{}", string),
             Expr::Symbol(ref token)  => write!(f, "{}", token),
             Expr::SExpr(ref values)  => {
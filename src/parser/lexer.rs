@@ -3,11 +3,12 @@
 use std;
 use std::borrow::ToOwned;
 use std::rc::Rc;
+use num::{BigInt, BigRational, Zero};
 use parser::tokens::{Token, SourceLocation, dummy_source};
 use parser::util::{SharedString, rcstr, rcstring};
 
 // --- Lexer: Error -------------------------------------------------------------
-const SYMBOL_CHARS: &'static str = "+-*/%\\=<>!?&_#$§^`.,:@";
+const SYMBOL_CHARS: &'static str = "+-*/%\\=<>!?&_#$§^.:@";
 
 pub type LexerResult<T> = Result<T, LexerError>;
 
@@ -21,29 +22,44 @@ pub enum LexerError {
         token: SharedString,  // result of curr_repr
         location: SourceLocation
     },
-    InvalidInteger {
+    InvalidNumber {
         input: SharedString,
         location: SourceLocation
     }
 }
 
-impl std::fmt::Debug for LexerError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl LexerError {
+    /// Where the error occured
+    pub fn location(&self) -> &SourceLocation {
+        match *self {
+            LexerError::UnexpectedChar { ref location, .. } => location,
+            LexerError::UnknownToken { ref location, .. } => location,
+            LexerError::InvalidNumber { ref location, .. } => location
+        }
+    }
+
+    /// Describe the error without its location
+    pub fn message(&self) -> String {
         match *self {
-            LexerError::UnexpectedChar { ref expected, ref found, ref location } => {
-                write!(f, "unexpected character: expected `{}`, but found {} at {}",
-                       expected, found, location)
+            LexerError::UnexpectedChar { ref expected, ref found, .. } => {
+                format!("unexpected character: expected `{}`, but found {}", expected, found)
             },
-            LexerError::UnknownToken { ref token, ref location } => {
-                write!(f, "unknown token: `{}` at {}", token, location)
+            LexerError::UnknownToken { ref token, .. } => {
+                format!("unknown token: `{}`", token)
             },
-            LexerError::InvalidInteger { ref input, ref location } => {
-                write!(f, "invalid integer: `{}` at {}", input, location)
+            LexerError::InvalidNumber { ref input, .. } => {
+                format!("invalid number: `{}`", input)
             }
         }
     }
 }
 
+impl std::fmt::Debug for LexerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} at {}", self.message(), self.location())
+    }
+}
+
 macro_rules! unknown_token(
     ($token:expr; $location:expr) => (
         return Err(LexerError::UnknownToken {
@@ -55,7 +71,7 @@ macro_rules! unknown_token(
 
 macro_rules! invalid_number(
     ($input:expr; $location:expr) => (
-        return Err(LexerError::InvalidInteger {
+        return Err(LexerError::InvalidNumber {
             input: $input.clone(),
             location: $location
         })
@@ -65,7 +81,7 @@ macro_rules! invalid_number(
 // --- Lexer --------------------------------------------------------------------
 
 pub trait Lexer {
-    /// Get the source of the current token
+    /// Get the source of the current token, i.e. where it starts
     fn get_source(&self) -> SourceLocation;
 
     /// Get the next token
@@ -79,22 +95,30 @@ pub trait Lexer {
 /// Lexer for tokenize a file from disk/memory.
 pub struct FileLexer<'a> {
     source: &'a str,
+    input: SharedString,  // The source again, shared with the source locations
     file: SharedString,
     len: usize,
     pos: usize,
     curr: Option<char>,
-    lineno: usize
+    lineno: usize,
+    column: usize,
+
+    /// Where the token that is being read starts: line, column and offset
+    token_start: (usize, usize, usize)
 }
 
 impl<'a> FileLexer<'a> {
     pub fn new(source: &'a str, file: &'a str) -> FileLexer<'a> {
         FileLexer {
             source: source,
+            input: rcstr(source),
             file: rcstr(file),
             len: source.len(),
             pos: 0,
             curr: if source.len() > 0 { Some(source.char_at(0)) } else { None },
-            lineno: 1
+            lineno: 1,
+            column: 1,
+            token_start: (1, 1, 0)
         }
     }
 
@@ -107,21 +131,30 @@ impl<'a> FileLexer<'a> {
 
     /// Move on to the next char
     fn bump(&mut self) {
+        if self.curr == Some('\n') {
+            self.lineno += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
         self.curr = self.nextch();
-        self.pos += 1;
+        self.pos = self.next_pos();
 
         debug!("Moved on to {:?}", self.curr)
     }
 
+    /// Get the byte offset of the next char
+    fn next_pos(&self) -> usize {
+        match self.curr {
+            Some(c) => self.pos + c.len_utf8(),
+            None => self.len
+        }
+    }
+
     /// Get the next char if possible
     fn nextch(&self) -> Option<char> {
-        let mut new_pos = self.pos + 1;
-        // When encountering multi-byte UTF-8, we may stop in the middle
-        // of it. Fast forward till we see the next actual char or EOF
-        while !self.source.is_char_boundary(new_pos)
-            && self.pos < self.len {
-            new_pos += 1;
-        }
+        let new_pos = self.next_pos();
 
         if new_pos < self.len {
             Some(self.source.char_at(new_pos))
@@ -190,19 +223,46 @@ impl<'a> FileLexer<'a> {
     // --- Internal methods: Tokenizers -----------------------------------------
 
     /// Tokenize a number
+    ///
+    /// Numbers containing a `.` are floats, numbers containing a `/` are
+    /// rationals and everything else is an integer.
     fn tokenize_number(&mut self) -> LexerResult<Token> {
-        let sign = if self.curr == Some('-') {
+        let negative = if self.curr == Some('-') {
             self.bump();
-            -1.0
+            true
         } else {
-            1.0
+            false
         };
-        let number = self.collect(|c| c.is_numeric() || *c == '.');
+        let number = self.collect(|c| c.is_numeric() || *c == '.' || *c == '/');
+        let literal = if negative { format!("-{}", number) } else { (*number).clone() };
+
+        if number.contains_char('.') {
+            let number = if let Ok(f) = literal.parse() { f }
+                         else { invalid_number!(number; self.get_source()) };
+
+            Ok(Token::NUMBER(number))
+        } else if number.contains_char('/') {
+            let parts: Vec<&str> = literal.split('/').collect();
+            if parts.len() != 2 {
+                invalid_number!(number; self.get_source())
+            }
 
-        let number = if let Ok(m) = number.parse() { m }
-                      else { invalid_number!(number; self.get_source()) };
+            let numer = parts[0].parse::<BigInt>();
+            let denom = parts[1].parse::<BigInt>();
 
-        Ok(Token::NUMBER(sign * number))
+            match (numer, denom) {
+                (Ok(ref numer), Ok(ref denom)) if !denom.is_zero() => {
+                    Ok(Token::RATIONAL(BigRational::new(numer.clone(), denom.clone())))
+                },
+                _ => invalid_number!(number; self.get_source())
+            }
+        } else if let Ok(i) = literal.parse() {
+            Ok(Token::INTEGER(i))
+        } else if let Ok(i) = literal.parse::<BigInt>() {
+            Ok(Token::RATIONAL(BigRational::from_integer(i)))
+        } else {
+            invalid_number!(number; self.get_source())
+        }
     }
 
     /// Tokenize a symbol
@@ -210,7 +270,13 @@ impl<'a> FileLexer<'a> {
         let symbol = self.collect(|c| {
             c.is_alphanumeric() || SYMBOL_CHARS.contains_char(*c)
         });
-        Ok(Token::SYMBOL(symbol))
+
+        Ok(match &**symbol {
+            "true"  => Token::BOOL(true),
+            "false" => Token::BOOL(false),
+            "nil"   => Token::NIL,
+            _       => Token::SYMBOL(symbol.clone())
+        })
     }
 
     /// Tokenize a string
@@ -251,16 +317,34 @@ impl<'a> FileLexer<'a> {
             None => return Ok(Some(Token::EOF))
         };
 
+        self.token_start = (self.lineno, self.column, self.pos);
+
         let token = match c {
             c if c.is_numeric() => {
                 try!(self.tokenize_number())
             },
+            '#' if self.nextch() == Some('{') => {
+                self.bump();
+                self.bump();
+                Token::HASHBRACE
+            },
             c if c.is_alphanumeric() || SYMBOL_CHARS.contains_char(c) => {
                 try!(self.tokenize_symbol())
             },
             '"' => {
                 try!(self.tokenize_string())
             }
+            '\'' => { self.bump(); Token::QUOTE },
+            '`' => { self.bump(); Token::QUASIQUOTE },
+            ',' => {
+                self.bump();
+                if self.curr == Some('@') {
+                    self.bump();
+                    Token::UNQUOTE_SPLICING
+                } else {
+                    Token::UNQUOTE
+                }
+            },
             '(' => { self.bump(); Token::LPAREN },
             ')' => { self.bump(); Token::RPAREN },
             '{' => { self.bump(); Token::LBRACE },
@@ -271,8 +355,6 @@ impl<'a> FileLexer<'a> {
                 return Ok(None);
             },
             c if c.is_whitespace() => {
-                if c == '\n' { self.lineno += 1; }
-
                 self.bump();
                 return Ok(None);
             },
@@ -288,9 +370,15 @@ impl<'a> FileLexer<'a> {
 
 impl<'a> Lexer for FileLexer<'a> {
     fn get_source(&self) -> SourceLocation {
+        let (lineno, column, start) = self.token_start;
+
         SourceLocation {
             filename: self.file.clone(),
-            lineno: self.lineno
+            lineno: lineno,
+            column: column,
+            start: start,
+            end: self.pos,
+            input: self.input.clone()
         }
     }
 
@@ -350,6 +438,7 @@ impl Lexer for Vec<Token> {
 
 #[cfg(test)]
 mod tests {
+    use num::{BigInt, BigRational, FromPrimitive};
     use parser::lexer::{Lexer, FileLexer};
     use parser::tokens::Token;
     use parser::tokens::Token::*;
@@ -371,10 +460,38 @@ mod tests {
                    vec![SYMBOL(rcstr("/"))]);
     }
 
+    #[test]
+    fn test_constants() {
+        assert_eq!(tokenize("true false nil"),
+                   vec![BOOL(true), BOOL(false), NIL]);
+    }
+
     #[test]
     fn test_number() {
         assert_eq!(tokenize("123"),
-                   vec![NUMBER(123.)]);
+                   vec![INTEGER(123)]);
+        assert_eq!(tokenize("1.5"),
+                   vec![NUMBER(1.5)]);
+    }
+
+    #[test]
+    fn test_number_exact() {
+        let big: BigInt = "123456789012345678901234567890".parse().unwrap();
+
+        assert_eq!(tokenize("123456789012345678901234567890"),
+                   vec![RATIONAL(BigRational::from_integer(big))]);
+        assert_eq!(tokenize("3/4"),
+                   vec![RATIONAL(BigRational::new(FromPrimitive::from_i64(3).unwrap(),
+                                                  FromPrimitive::from_i64(4).unwrap()))]);
+    }
+
+    #[test]
+    fn test_number_invalid() {
+        let message = |src: &'static str| FileLexer::new(src, "<test>").tokenize().err().unwrap().message();
+
+        assert_eq!(message("1.2.3"), "invalid number: `1.2.3`");
+        assert_eq!(message("1/0"), "invalid number: `1/0`");
+        assert_eq!(message("1/2/3"), "invalid number: `1/2/3`");
     }
 
     /*#[test]
@@ -383,6 +500,46 @@ mod tests {
                    vec![NUMBER(-123)]);
     }*/
 
+    #[test]
+    fn test_quotes() {
+        assert_eq!(tokenize("'a `(a ,b ,@c)"),
+                   vec![QUOTE, SYMBOL(rcstr("a")),
+                        QUASIQUOTE, LPAREN, SYMBOL(rcstr("a")),
+                        UNQUOTE, SYMBOL(rcstr("b")),
+                        UNQUOTE_SPLICING, SYMBOL(rcstr("c")), RPAREN]);
+    }
+
+    #[test]
+    fn test_map() {
+        assert_eq!(tokenize("#{1 2} #a"),
+                   vec![HASHBRACE, INTEGER(1), INTEGER(2), RBRACE, SYMBOL(rcstr("#a"))]);
+    }
+
+    #[test]
+    fn test_source_location() {
+        let mut lexer = FileLexer::new("(+ 1\n   22)", "<test>");
+
+        for _ in 0..3 {
+            lexer.next_token().ok().unwrap();
+        }
+        assert_eq!(format!("{}", lexer.get_source()), "<test>:1:4");
+
+        lexer.next_token().ok().unwrap();
+        assert_eq!(format!("{}", lexer.get_source()), "<test>:2:4");
+    }
+
+    #[test]
+    fn test_source_span() {
+        let mut lexer = FileLexer::new("(\"äö\" abc)", "<test>");
+
+        lexer.next_token().ok().unwrap();
+        lexer.next_token().ok().unwrap();
+        lexer.next_token().ok().unwrap();
+
+        let location = lexer.get_source();
+        assert_eq!((location.column, location.start, location.end), (7, 8, 11));
+    }
+
     #[test]
     fn test_parens() {
         assert_eq!(tokenize("("),
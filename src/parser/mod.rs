@@ -6,6 +6,7 @@ use std::collections::LinkedList;
 use parser::ast::{Expr, ExprNode};
 use parser::tokens::{Token, SourceLocation};
 use parser::lexer::{Lexer, FileLexer, LexerError};
+use parser::util::rcstr;
 
 pub mod util;
 pub mod tokens;
@@ -25,24 +26,35 @@ pub enum ParserError {
     FromLexer(LexerError)
 }
 
-impl std::fmt::Debug for ParserError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl ParserError {
+    /// Where the error occured
+    pub fn location(&self) -> &SourceLocation {
+        match *self {
+            ParserError::UnexpectedToken { ref location, .. } => location,
+            ParserError::FromLexer(ref lxerr) => lxerr.location()
+        }
+    }
+
+    /// Describe the error without its location
+    pub fn message(&self) -> String {
         match *self {
-            ParserError::UnexpectedToken { ref found, ref expected, ref location } => {
+            ParserError::UnexpectedToken { ref found, ref expected, .. } => {
                 match *expected {
-                    Some(ref expected) => {
-                        write!(f, "expected {}, found `{}` at {}", expected, found, location)
-                    },
-                    None => {
-                        write!(f, "unexpected token: `{}` at {}", found, location)
-                    }
+                    Some(ref expected) => format!("expected {}, found `{}`", expected, found),
+                    None => format!("unexpected token: `{}`", found)
                 }
             },
-            ParserError::FromLexer(ref lxerr) => write!(f, "{:?}", lxerr)
+            ParserError::FromLexer(ref lxerr) => lxerr.message()
         }
     }
 }
 
+impl std::fmt::Debug for ParserError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} at {}", self.message(), self.location())
+    }
+}
+
 impl std::error::FromError<LexerError> for ParserError {
     fn from_error(err: LexerError) -> ParserError {
         ParserError::FromLexer(err)
@@ -77,6 +89,20 @@ macro_rules! unexpected(
 
 // --- Parser -------------------------------------------------------------------
 
+/// Turn an expression into a QExpr: `(a b)` becomes `{a b}`, `x` becomes `{x}`
+fn quote_expr(expr: ExprNode) -> ExprNode {
+    let ExprNode { value, location } = expr;
+
+    match value {
+        Expr::SExpr(exprs) => ExprNode::new(Expr::QExpr(exprs), location),
+        value => {
+            let quoted = vec![ExprNode::new(value, location.clone())];
+            ExprNode::new(Expr::QExpr(quoted), location)
+        }
+    }
+}
+
+
 /// Lispy Parser
 pub struct Parser<'a> {
     location: SourceLocation,
@@ -121,6 +147,11 @@ impl<'a> Parser<'a> {
         self.location.clone()
     }
 
+    /// Extend `location` up to the end of the current token
+    fn extend_location(&self, location: &mut SourceLocation) {
+        location.end = self.lexer.get_source().end;
+    }
+
     /// Expect the current token to be `tok` and continue or fail
     fn expect(&mut self, tok: &Token) -> ParserResult<()> {
         if self.token == *tok {
@@ -161,7 +192,9 @@ impl<'a> Parser<'a> {
         let location = self.update_location();
 
         let number = match self.token {
-            Token::NUMBER(i) => Expr::Number(i),
+            Token::NUMBER(n)  => Expr::Number(n),
+            Token::INTEGER(i) => Expr::Integer(i),
+            Token::RATIONAL(ref r) => Expr::Rational(r.clone()),
             _ => unexpected!(self.token, instead of "a number"; location)
         };
         try!(self.bump());
@@ -169,6 +202,20 @@ impl<'a> Parser<'a> {
         Ok(ExprNode::new(number, location))
     }
 
+    /// Parse a boolean or nil
+    fn parse_constant(&mut self) -> ParserResult<ExprNode> {
+        let location = self.update_location();
+
+        let constant = match self.token {
+            Token::BOOL(b) => Expr::Bool(b),
+            Token::NIL     => Expr::Nil,
+            _ => unexpected!(self.token, instead of "a constant"; location)
+        };
+        try!(self.bump());
+
+        Ok(ExprNode::new(constant, location))
+    }
+
     /// Parse a string
     fn parse_string(&mut self) -> ParserResult<ExprNode> {
         let location = self.update_location();
@@ -197,7 +244,7 @@ impl<'a> Parser<'a> {
 
     /// Parse a SExpr
     fn parse_sexpr(&mut self) -> ParserResult<ExprNode> {
-        let location = self.update_location();
+        let mut location = self.update_location();
 
         try!(self.expect(&Token::LPAREN));
 
@@ -210,6 +257,7 @@ impl<'a> Parser<'a> {
             exprs.push(expr);
         }
 
+        self.extend_location(&mut location);
         try!(self.expect(&Token::RPAREN));
 
         Ok(ExprNode::new(Expr::SExpr(exprs), location))
@@ -217,7 +265,7 @@ impl<'a> Parser<'a> {
 
     /// Parse a QExpr
     fn parse_qexpr(&mut self) -> ParserResult<ExprNode> {
-        let location = self.update_location();
+        let mut location = self.update_location();
 
         try!(self.expect(&Token::LBRACE));
 
@@ -230,19 +278,84 @@ impl<'a> Parser<'a> {
             exprs.push(expr);
         }
 
+        self.extend_location(&mut location);
         try!(self.expect(&Token::RBRACE));
 
         Ok(ExprNode::new(Expr::QExpr(exprs), location))
     }
 
+    /// Parse a map literal
+    ///
+    /// `#{k1 v1 k2 v2}` is desugared into `(hash-map k1 v1 k2 v2)`.
+    fn parse_map(&mut self) -> ParserResult<ExprNode> {
+        let mut location = self.update_location();
+
+        try!(self.expect(&Token::HASHBRACE));
+
+        let mut exprs = vec![ExprNode::new(Expr::Symbol(rcstr("hash-map")), location.clone())];
+        while self.token != Token::RBRACE {
+            exprs.push(try!(self.parse_expr()));
+        }
+
+        self.extend_location(&mut location);
+        try!(self.expect(&Token::RBRACE));
+
+        Ok(ExprNode::new(Expr::SExpr(exprs), location))
+    }
+
+    /// Parse a quoted expression
+    ///
+    /// The quote forms are desugared:
+    ///
+    /// - `'x` becomes `{x}` and `'(a b)` becomes `{a b}`
+    /// - `` `x `` becomes `(quasiquote {x})`, quoting `x` like `'` does
+    /// - `,x` becomes `(unquote x)`
+    /// - `,@x` becomes `(unquote-splicing x)`
+    fn parse_quoted(&mut self) -> ParserResult<ExprNode> {
+        let location = self.update_location();
+        let quote = self.token.clone();
+        try!(self.bump());
+
+        let expr = try!(self.parse_expr());
+
+        let symbol = match quote {
+            Token::QUOTE            => return Ok(quote_expr(expr)),
+            Token::QUASIQUOTE       => "quasiquote",
+            Token::UNQUOTE          => "unquote",
+            Token::UNQUOTE_SPLICING => "unquote-splicing",
+            _ => unexpected!(quote, instead of "a quote"; location)
+        };
+
+        let argument = if quote == Token::QUASIQUOTE {
+            quote_expr(expr)
+        } else {
+            expr
+        };
+
+        Ok(ExprNode::new(Expr::SExpr(vec![
+            ExprNode::new(Expr::Symbol(rcstr(symbol)), location.clone()),
+            argument
+        ]), location))
+    }
+
     /// Parse a single expression
     fn parse_expr(&mut self) -> ParserResult<ExprNode> {
         let stmt = match self.token {
-            Token::NUMBER(_) => try!(self.parse_number()),
+            Token::NUMBER(_)  => try!(self.parse_number()),
+            Token::INTEGER(_) => try!(self.parse_number()),
+            Token::RATIONAL(_) => try!(self.parse_number()),
+            Token::BOOL(_)    => try!(self.parse_constant()),
+            Token::NIL        => try!(self.parse_constant()),
             Token::STRING(_)  => try!(self.parse_string()),
             Token::SYMBOL(_)  => try!(self.parse_symbol()),
             Token::LPAREN     => try!(self.parse_sexpr()),
             Token::LBRACE     => try!(self.parse_qexpr()),
+            Token::HASHBRACE  => try!(self.parse_map()),
+
+            Token::QUOTE      |
+            Token::QUASIQUOTE |
+            Token::UNQUOTE    |
+            Token::UNQUOTE_SPLICING => try!(self.parse_quoted()),
 
             _ => unexpected!(self.token, instead of "an expression"; self.location.clone())
         };
@@ -295,4 +408,33 @@ mod tests {
             )
         )
     }
+
+    #[test]
+    fn test_quasiquote() {
+        assert_eq!(
+            parse(
+                vec![QUASIQUOTE, LPAREN, SYMBOL(rcstr("a")), UNQUOTE, SYMBOL(rcstr("b")), RPAREN],
+                |p| p.parse_expr().unwrap()
+            ),
+            ExprNode::new(
+                Expr::SExpr(vec![
+                    ExprNode::new(Expr::Symbol(rcstr("quasiquote")), dummy_source()),
+                    ExprNode::new(
+                        Expr::QExpr(vec![
+                            ExprNode::new(Expr::Symbol(rcstr("a")), dummy_source()),
+                            ExprNode::new(
+                                Expr::SExpr(vec![
+                                    ExprNode::new(Expr::Symbol(rcstr("unquote")), dummy_source()),
+                                    ExprNode::new(Expr::Symbol(rcstr("b")), dummy_source())
+                                ]),
+                                dummy_source()
+                            )
+                        ]),
+                        dummy_source()
+                    )
+                ]),
+                dummy_source()
+            )
+        )
+    }
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
 //! Tokens
 
 use std::fmt;
+use num::BigRational;
 use parser::util::{rcstr, SharedString};
 
 // --- Token --------------------------------------------------------------------
@@ -12,10 +13,20 @@ pub enum Token {
     RPAREN,
     LBRACE,
     RBRACE,
+    HASHBRACE,
+
+    QUOTE,
+    QUASIQUOTE,
+    UNQUOTE,
+    UNQUOTE_SPLICING,
 
     STRING(SharedString),
     SYMBOL(SharedString),
     NUMBER(f64),
+    INTEGER(i64),
+    RATIONAL(BigRational),  // Exact numbers that don't fit into an INTEGER
+    BOOL(bool),
+    NIL,
 
     EOF,
     PLACEHOLDER
@@ -28,10 +39,20 @@ impl fmt::Display for Token {
             Token::RPAREN        => write!(f, ")"),
             Token::LBRACE        => write!(f, "{{"),
             Token::RBRACE        => write!(f, "}}"),
+            Token::HASHBRACE     => write!(f, "#{{"),
+
+            Token::QUOTE            => write!(f, "'"),
+            Token::QUASIQUOTE       => write!(f, "`"),
+            Token::UNQUOTE          => write!(f, ","),
+            Token::UNQUOTE_SPLICING => write!(f, ",@"),
 
             Token::STRING(ref s) => write!(f, "\"{}\"", s.escape_default()),
             Token::SYMBOL(ref s) => write!(f, "{}", s),
             Token::NUMBER(n)     => write!(f, "{}", n),
+            Token::INTEGER(i)    => write!(f, "{}", i),
+            Token::RATIONAL(ref r) => write!(f, "{}", r),
+            Token::BOOL(b)       => write!(f, "{}", b),
+            Token::NIL           => write!(f, "nil"),
 
             Token::EOF           => write!(f, "EOF"),
             Token::PLACEHOLDER   => write!(f, "PLACEHOLDER")
@@ -49,15 +70,37 @@ impl fmt::Debug for Token {
 // --- Source location ----------------------------------------------------------
 
 /// Represntation of a location in the input
-#[derive(PartialEq, Eq, Clone)]
+#[derive(Clone)]
 pub struct SourceLocation {
     pub filename: SharedString,
-    pub lineno: usize
+    pub lineno: usize,
+    pub column: usize,
+
+    /// Byte offsets of the start and the end in the input
+    pub start: usize,
+    pub end: usize,
+
+    /// The whole input, used to show the location in diagnostics
+    pub input: SharedString
 }
 
+// The input isn't compared: it's the same for all locations in a file, and
+// comparing it would take time proportional to its size
+impl PartialEq for SourceLocation {
+    fn eq(&self, other: &SourceLocation) -> bool {
+        self.filename == other.filename &&
+        self.lineno == other.lineno &&
+        self.column == other.column &&
+        self.start == other.start &&
+        self.end == other.end
+    }
+}
+
+impl Eq for SourceLocation {}
+
 impl fmt::Display for SourceLocation {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}:{}", self.filename, self.lineno)
+        write!(f, "{}:{}:{}", self.filename, self.lineno, self.column)
     }
 }
 
@@ -66,6 +109,10 @@ impl fmt::Display for SourceLocation {
 pub fn dummy_source() -> SourceLocation {
     SourceLocation {
         filename: rcstr("<input>"),
-        lineno: 0
+        lineno: 0,
+        column: 0,
+        start: 0,
+        end: 0,
+        input: rcstr("")
     }
 }
\ No newline at end of file
@@ -0,0 +1,147 @@
+//! Lexical addressing
+//!
+//! When a lambda is created, the symbols in its body that name its arguments
+//! are replaced by their lexical address, the argument's slot (see
+//! `LEnv::get_local`). Evaluating them then doesn't search the environments
+//! by name.
+//!
+//! All other variables are still looked up by name. Global variables may be
+//! defined after the lambda, and the environments of enclosing lambdas may
+//! get new variables from `=` that shadow the ones seen now. Variables in
+//! code nested deeper than the depth limit (see `stack::nested`) aren't
+//! resolved either.
+
+use std::rc::Rc;
+use lval::LVal;
+use lenv::LEnv;
+use symbol::Symbol;
+use stack;
+use builtin::flags::EVALUATES_BRANCHES;
+
+
+/// Resolve the local variables in the body of a lambda created in `env`
+pub fn resolve_lambda(env: &LEnv, formals: &LVal, body: LVal) -> LVal {
+    // The arguments are bound in order, `...` doesn't get a slot
+    let mut arguments: Vec<Symbol> = vec![];
+    for formal in formals.values().iter() {
+        let name = formal.as_sym();
+        if name.as_str() != "..." && !arguments.contains(&name) {
+            arguments.push(name);
+        }
+    }
+
+    let scope = Scope {
+        env: env,
+        arguments: arguments
+    };
+
+    // The body is evaluated as a S-Expr
+    LVal::qexpr_of(scope.sexpr(&body.values()))
+}
+
+
+struct Scope<'a> {
+    /// The environment the lambda has been created in
+    env: &'a LEnv,
+
+    /// The lambda's arguments in slot order
+    arguments: Vec<Symbol>
+}
+
+impl<'a> Scope<'a> {
+    fn address(&self, name: Symbol) -> Option<(usize, usize)> {
+        self.arguments.iter().position(|&arg| arg == name).map(|slot| (0, slot))
+    }
+
+    fn expr(&self, value: &LVal) -> LVal {
+        match *value {
+            LVal::Sym(name) => match self.address(name) {
+                Some((depth, slot)) => LVal::Local(name, depth, slot),
+                None => value.clone()
+            },
+            LVal::SExpr(ref values, ref span) => match stack::nested(|| self.sexpr(values)) {
+                Some(values) => LVal::SExpr(Rc::new(values), span.clone()),
+                None => value.clone()
+            },
+
+            // Q-Exprs are data
+            ref value => value.clone()
+        }
+    }
+
+    fn sexpr(&self, values: &Vec<LVal>) -> Vec<LVal> {
+        // Builtins and macros are global, so a local name can't be one
+        let head = match values.get(0) {
+            Some(&LVal::Sym(name)) if self.address(name).is_none() => self.env.get(name),
+            _ => LVal::Nil
+        };
+
+        let branches = match head {
+            // Macros get their arguments as they are written
+            LVal::Macro { .. } => return values.clone(),
+            LVal::Builtin(ref builtin) => builtin.has(EVALUATES_BRANCHES),
+            _ => false
+        };
+
+        values.iter().enumerate().map(|(i, value)| match *value {
+            LVal::QExpr(ref branch) if branches && i > 1 => {
+                match stack::nested(|| self.sexpr(&branch.to_shared())) {
+                    Some(branch) => LVal::qexpr_of(branch),
+                    None => value.clone()
+                }
+            },
+            ref value => self.expr(value)
+        }).collect()
+    }
+}
+
+
+// --- Tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use eval::eval;
+    use lval::LVal;
+    use symbol::Symbol;
+    use testing::{env, run_in, run_with};
+
+    #[test]
+    fn resolve_arguments() {
+        let mut env = env(true);
+
+        match run_in(&mut env, &["(def {f} (\\ {x} {\\ {y} {if y {x} {y}}}))", "(f 1)"], &eval) {
+            LVal::Function { body, .. } => {
+                assert!(body[0] == LVal::sym("if"));
+                assert!(body[1] == LVal::Local(Symbol::intern("y"), 0, 0));
+                assert!(body[2] == LVal::qexpr_of(vec![LVal::sym("x")]));
+            },
+            other => panic!("not a function: {}", other)
+        }
+    }
+
+    #[test]
+    fn resolve_keeps_data() {
+        assert_eq!(
+            run_with(true, &["((\\ {x} {list {x} x}) 1)"]),
+            LVal::qexpr_of(vec![LVal::qexpr_of(vec![LVal::sym("x")]), LVal::int(1)])
+        )
+    }
+
+    #[test]
+    fn resolve_shadowed_outer() {
+        // `=` defines a local `x`, so the outer `x` mustn't be resolved
+        assert_eq!(
+            run_with(true, &["(((\\ {x} {\\ {y} {do (= {x} y) x}}) 1) 2)"]),
+            LVal::int(2)
+        )
+    }
+
+    #[test]
+    fn resolve_shadowed_later() {
+        // `g` is created before `=` defines a local `x` in its environment
+        assert_eq!(
+            run_with(true, &["((\\ {x} {(\\ {y} {do (= {g} (\\ {_} {x})) (= {x} 5) (g 0)}) 1}) 0)"]),
+            LVal::int(5)
+        )
+    }
+}
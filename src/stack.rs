@@ -0,0 +1,148 @@
+//! The evaluator's call stack
+//!
+//! Every lambda call in progress has a frame on the stack. Calls in tail
+//! position replace the caller's frame, just like they reuse its Rust stack
+//! frame in `eval`.
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::borrow::ToOwned;
+use parser::tokens::SourceLocation;
+
+
+/// A function call in progress
+#[derive(PartialEq, Clone)]
+pub struct StackFrame {
+    /// The name the function has been called by
+    pub name: String,
+
+    /// The location of the call, if known
+    pub location: Option<SourceLocation>
+}
+
+impl StackFrame {
+    pub fn new(name: &str, location: Option<SourceLocation>) -> StackFrame {
+        StackFrame {
+            name: name.to_owned(),
+            location: location
+        }
+    }
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some(ref location) => write!(f, "`{}` at {}", self.name, location),
+            None => write!(f, "`{}`", self.name)
+        }
+    }
+}
+
+
+thread_local!(static STACK: RefCell<Vec<StackFrame>> = RefCell::new(vec![]));
+
+
+/// Enter a function
+pub fn push(frame: StackFrame) {
+    STACK.with(|stack| stack.borrow_mut().push(frame));
+}
+
+/// Replace the innermost frame, used for tail calls
+pub fn replace_top(frame: StackFrame) {
+    STACK.with(|stack| {
+        let mut stack = stack.borrow_mut();
+        stack.pop();
+        stack.push(frame);
+    });
+}
+
+/// Leave all functions entered since the stack had the given depth
+pub fn truncate(depth: usize) {
+    STACK.with(|stack| stack.borrow_mut().truncate(depth));
+}
+
+/// The number of functions in progress
+pub fn depth() -> usize {
+    STACK.with(|stack| stack.borrow().len())
+}
+
+/// Get the innermost call, if any
+pub fn innermost() -> Option<StackFrame> {
+    STACK.with(|stack| stack.borrow().last().map(|frame| frame.clone()))
+}
+
+/// Get a copy of the stack, innermost call first
+pub fn backtrace() -> Vec<StackFrame> {
+    STACK.with(|stack| stack.borrow().iter().rev().map(|frame| frame.clone()).collect())
+}
+
+
+/// The default limit for nested evaluations, see `set_max_depth`
+pub const DEFAULT_MAX_DEPTH: usize = 10000;
+
+/// The native stack reserved for every nested evaluation, see `stack_size`
+///
+/// A nested evaluation goes through `eval`, `eval_sexpr` and `apply`, whose
+/// frames take a few kilobytes in debug builds. This leaves room for that and
+/// for builtins like `map` calling back into the evaluator.
+pub const STACK_PER_LEVEL: usize = 16 * 1024;
+
+/// The native stack size needed to evaluate up to `depth` nested levels
+///
+/// Code is evaluated on a thread with this much stack, as the main thread's
+/// stack can't be resized.
+pub fn stack_size(depth: usize) -> usize {
+    // Leave room for the REPL and for the error handling at the limit
+    (depth + 64) * STACK_PER_LEVEL
+}
+
+thread_local!(static EVAL_DEPTH: Cell<usize> = Cell::new(0));
+thread_local!(static MAX_DEPTH: Cell<usize> = Cell::new(DEFAULT_MAX_DEPTH));
+
+/// Set how deeply evaluations may nest
+///
+/// Every nested evaluation uses native stack space, so a runaway recursion
+/// is stopped with an error before the stack overflows.
+pub fn set_max_depth(depth: usize) {
+    MAX_DEPTH.with(|max| max.set(depth));
+}
+
+pub fn max_depth() -> usize {
+    MAX_DEPTH.with(|max| max.get())
+}
+
+/// Start a nested evaluation
+///
+/// Returns `false` if that would exceed the limit, the evaluation must not
+/// be started then.
+pub fn enter_eval() -> bool {
+    EVAL_DEPTH.with(|depth| {
+        if depth.get() >= max_depth() {
+            return false
+        }
+
+        depth.set(depth.get() + 1);
+        true
+    })
+}
+
+/// Finish a nested evaluation started with `enter_eval`
+pub fn leave_eval() {
+    EVAL_DEPTH.with(|depth| depth.set(depth.get() - 1));
+}
+
+/// Run `f` as a nested evaluation
+///
+/// Returns `None` without running `f` if that would exceed the limit. Used by
+/// the passes over code and values that recurse as deeply as they are
+/// nested.
+pub fn nested<T, F: FnOnce() -> T>(f: F) -> Option<T> {
+    if !enter_eval() {
+        return None
+    }
+
+    let result = f();
+    leave_eval();
+
+    Some(result)
+}
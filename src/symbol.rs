@@ -0,0 +1,113 @@
+//! Interned symbols
+//!
+//! Every symbol name is stored once in a global table and referred to by its
+//! index. Comparing and hashing symbols only looks at the index, so
+//! environment lookups don't have to hash strings and cloning a symbol
+//! doesn't allocate.
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+
+
+/// An interned symbol name
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Get the symbol for a name, adding it to the table if it's new
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|interner| interner.borrow_mut().intern(name))
+    }
+
+    /// Get the symbol's name
+    pub fn as_str(&self) -> &'static str {
+        INTERNER.with(|interner| interner.borrow().names[self.0 as usize])
+    }
+}
+
+// Symbols are ordered by name so maps with symbol keys are sorted
+// alphabetically, no matter in which order the symbols have been interned
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Symbol) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Symbol) -> Ordering {
+        if self == other {
+            Ordering::Equal
+        } else {
+            self.as_str().cmp(other.as_str())
+        }
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+
+/// The symbol table
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>
+}
+
+impl Interner {
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol
+        }
+
+        // Interned names live as long as the program, so the string is leaked
+        // to hand out `&'static str`s
+        let owned = name.to_string();
+        let name: &'static str = unsafe { mem::transmute(&owned[..]) };
+        mem::forget(owned);
+
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name);
+        self.symbols.insert(name, symbol);
+
+        symbol
+    }
+}
+
+thread_local!(static INTERNER: RefCell<Interner> = RefCell::new(Interner {
+    symbols: HashMap::new(),
+    names: vec![]
+}));
+
+
+// --- Tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::Symbol;
+
+    #[test]
+    fn intern_same_name() {
+        assert!(Symbol::intern("foo") == Symbol::intern("foo"));
+        assert!(Symbol::intern("foo") != Symbol::intern("bar"));
+        assert_eq!(Symbol::intern("foo").as_str(), "foo");
+    }
+
+    #[test]
+    fn order_by_name() {
+        let b = Symbol::intern("b");
+        let a = Symbol::intern("a");
+        assert!(a < b);
+    }
+}
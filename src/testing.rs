@@ -0,0 +1,43 @@
+//! Helpers shared by the tests
+
+use builtin;
+use eval::eval;
+use lval::LVal;
+use lenv::LEnv;
+use parser::Parser;
+
+
+/// Create an environment with the builtins and, if `prelude` is set, the
+/// standard prelude
+pub fn env(prelude: bool) -> LEnv {
+    let mut env = LEnv::new();
+    builtin::initialize(&mut env, prelude);
+    env
+}
+
+/// Parse a line of code into a S-Expr of its expressions
+pub fn parse(line: &str) -> LVal {
+    LVal::from_ast(Parser::parse(line, "<test>").ok().expect("parse error"))
+}
+
+/// Evaluate lines one after another with `evaluate` and return the last
+/// result
+pub fn run_in(env: &mut LEnv, lines: &[&str], evaluate: &Fn(&mut LEnv, LVal) -> LVal) -> LVal {
+    let mut result = LVal::sexpr();
+
+    for line in lines {
+        result = evaluate(env, parse(*line));
+    }
+
+    result
+}
+
+/// Evaluate lines in a new environment without the prelude
+pub fn run(lines: &[&str]) -> LVal {
+    run_with(false, lines)
+}
+
+/// Evaluate lines in a new environment
+pub fn run_with(prelude: bool, lines: &[&str]) -> LVal {
+    run_in(&mut env(prelude), lines, &eval)
+}
@@ -1,6 +1,9 @@
 use std::fmt;
+use std::iter::repeat;
 
-use ansi_term::Colour::Red;
+use ansi_term::Colour::{Blue, Red};
+
+use parser::tokens::SourceLocation;
 
 
 pub fn print_error(msg: &str) {
@@ -8,6 +11,59 @@ pub fn print_error(msg: &str) {
 }
 
 
+/// Print an error together with the source it points to
+pub fn print_error_at(msg: &str, location: &SourceLocation) {
+    print_error(&format!("{}\n{}", msg, source_snippet(location)));
+}
+
+
+/// Show the line `location` is in with the location underlined, like this:
+///
+/// ```text
+///  --> file.lisp:2:4
+///   |
+/// 2 |    (/ 1 0))
+///   |    ^^^^^^^
+/// ```
+///
+/// Locations spanning multiple lines are underlined up to the end of the
+/// first line. If the input isn't available, only the location is shown.
+pub fn source_snippet(location: &SourceLocation) -> String {
+    let arrow = format!("{}", Blue.paint("-->"));
+
+    if location.lineno == 0 {
+        return format!(" {} {}", arrow, location)
+    }
+
+    let line = match location.input.lines().nth(location.lineno - 1) {
+        Some(line) => line,
+        _ => return format!(" {} {}", arrow, location)
+    };
+
+    let lineno = format!("{}", location.lineno);
+    let gutter = repeat(' ').take(lineno.len()).collect::<String>();
+    let bar = format!("{}", Blue.paint("|"));
+
+    // Keep tabs, so the underline lines up with the source
+    let indent: String = line.chars()
+        .take(location.column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    let rest = line.chars().count() - indent.chars().count();
+    let mut width = location.input[location.start..location.end].chars().count();
+    if width > rest { width = rest }
+    if width < 1 { width = 1 }
+    let carets = repeat('^').take(width).collect::<String>();
+
+    format!("{}{} {}\n{} {}\n{} {} {}\n{} {} {}{}",
+            gutter, arrow, location,
+            gutter, bar,
+            Blue.paint(&lineno[..]), bar, line,
+            gutter, bar, indent, Red.paint(&carets[..]))
+}
+
+
 pub fn stringify_vec<T: fmt::Debug>(v: &Vec<T>) -> String {
     v.iter()
         .map(|v| format!("{:?}", v))
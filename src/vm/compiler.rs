@@ -0,0 +1,198 @@
+//! The bytecode compiler
+//!
+//! Turns an expression into a flat list of instructions for the virtual
+//! machine. Symbols naming local variables are resolved to slots, all other
+//! symbols are looked up by name when the code runs.
+
+use std::fmt;
+use lval::{LVal, Span};
+use symbol::Symbol;
+use eval::depth_exceeded;
+use stack;
+
+
+/// An instruction
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Op {
+    /// Push a constant
+    Const(usize),
+
+    /// Push the value of a local variable
+    Local(usize),
+
+    /// Push the value of a variable looked up by name
+    Global(Symbol),
+
+    /// If the value on top of the stack is a macro, expand the S-Expr of the
+    /// given call site with it and continue at the given instruction
+    Macro(usize, usize),
+
+    /// Evaluate a S-Expr: call the function below the given number of
+    /// arguments, at the given call site
+    ///
+    /// Without arguments, the value is left as it is.
+    Call(usize, usize),
+
+    /// Return the value on top of the stack
+    Return
+}
+
+
+/// A S-Expr in the code
+pub struct CallSite {
+    /// The name of the called function, used in backtraces
+    pub name: String,
+
+    /// The unevaluated arguments, passed to macros
+    pub args: Vec<LVal>,
+
+    /// Where the S-Expr has been written
+    pub span: Span,
+
+    /// Whether the value of the S-Expr is the value of the whole code
+    pub tail: bool
+}
+
+
+/// Compiled code
+pub struct Code {
+    pub ops: Vec<Op>,
+    pub constants: Vec<LVal>,
+    pub sites: Vec<CallSite>,
+
+    /// The local variables in slot order
+    pub locals: Vec<Symbol>
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, op) in self.ops.iter().enumerate() {
+            try!(match *op {
+                Op::Const(c) => writeln!(f, "{:4} const {}", i, self.constants[c]),
+                Op::Local(slot) => writeln!(f, "{:4} local {} ({})", i, slot, self.locals[slot]),
+                Op::Global(name) => writeln!(f, "{:4} global {}", i, name),
+                Op::Macro(_, end) => writeln!(f, "{:4} macro? -> {}", i, end),
+                Op::Call(argc, site) => {
+                    let tail = if self.sites[site].tail { " (tail)" } else { "" };
+                    writeln!(f, "{:4} call `{}` with {}{}", i, self.sites[site].name, argc, tail)
+                },
+                Op::Return => writeln!(f, "{:4} return", i)
+            });
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Compile an expression whose local variables are `locals`
+pub fn compile(expr: &LVal, locals: &[Symbol]) -> Code {
+    let mut compiler = Compiler {
+        code: Code {
+            ops: vec![],
+            constants: vec![],
+            sites: vec![],
+            locals: locals.to_vec()
+        }
+    };
+
+    compiler.expr(expr, true);
+    compiler.code.ops.push(Op::Return);
+
+    compiler.code
+}
+
+
+struct Compiler {
+    code: Code
+}
+
+impl Compiler {
+    fn expr(&mut self, expr: &LVal, tail: bool) {
+        match *expr {
+            LVal::Sym(name) | LVal::Local(name, _, _) => {
+                let op = match self.code.locals.iter().position(|&local| local == name) {
+                    Some(slot) => Op::Local(slot),
+                    None => Op::Global(name)
+                };
+
+                self.code.ops.push(op);
+            },
+            LVal::SExpr(ref values, ref span) if values.len() > 0 => {
+                // Code nested too deeply evaluates to the error `eval` gives
+                if stack::nested(|| self.sexpr(values, span, tail)).is_none() {
+                    let constant = self.code.constants.len();
+                    self.code.constants.push(depth_exceeded("<compiler>"));
+                    self.code.ops.push(Op::Const(constant));
+                }
+            },
+            ref value => {
+                let constant = self.code.constants.len();
+                self.code.constants.push(value.clone());
+                self.code.ops.push(Op::Const(constant));
+            }
+        }
+    }
+
+    /// Compile a S-Expr like `eval_sexpr` evaluates it: the first value is
+    /// evaluated, then checked for being a macro, then the arguments are
+    /// evaluated and the function is called
+    fn sexpr(&mut self, values: &Vec<LVal>, span: &Span, tail: bool) {
+        let name = match values[0] {
+            LVal::Sym(name) | LVal::Local(name, _, _) => name.as_str(),
+            _ => "<lambda>"
+        };
+
+        let site = self.code.sites.len();
+        self.code.sites.push(CallSite {
+            name: name.to_string(),
+            args: values[1..].to_vec(),
+            span: span.clone(),
+            tail: tail
+        });
+
+        self.expr(&values[0], false);
+
+        // The end of the S-Expr isn't known yet
+        let check = self.code.ops.len();
+        self.code.ops.push(Op::Macro(site, 0));
+
+        for value in &values[1..] {
+            self.expr(value, false);
+        }
+
+        self.code.ops.push(Op::Call(values.len() - 1, site));
+
+        let end = self.code.ops.len();
+        self.code.ops[check] = Op::Macro(site, end);
+    }
+}
+
+
+// --- Tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use lval::LVal;
+    use symbol::Symbol;
+    use super::{compile, Op};
+
+    #[test]
+    fn compile_locals() {
+        let x = Symbol::intern("x");
+        let code = compile(
+            &LVal::sexpr_of(vec![LVal::sym("+"), LVal::sym("x"), LVal::int(1)]),
+            &[x]
+        );
+
+        assert_eq!(code.ops, vec![
+            Op::Global(Symbol::intern("+")),
+            Op::Macro(0, 5),
+            Op::Local(0),
+            Op::Const(0),
+            Op::Call(2, 0),
+            Op::Return
+        ]);
+        assert!(code.sites[0].tail);
+    }
+}
@@ -0,0 +1,467 @@
+//! The virtual machine
+//!
+//! An alternative to the tree-walking evaluator in `eval`: expressions are
+//! compiled to bytecode (see `compiler`) and run on a stack machine. Function
+//! bodies are compiled on their first call, their arguments live in slots
+//! instead of being looked up by name.
+//!
+//! `eval` stays the reference implementation, both have to give the same
+//! results. Builtins that call back into mlisp code, like `map`, still use
+//! the evaluator.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use lval::{LVal, LBuiltin, Span};
+use lenv::LEnv;
+use eval::{expand_macro, bind_arguments, depth_exceeded, call_builtin};
+use fuel;
+use parser::tokens::SourceLocation;
+use stack;
+use stack::StackFrame;
+use symbol::Symbol;
+use builtin::flags::WRITES_ENV;
+use self::compiler::{compile, Code, CallSite, Op};
+
+pub mod compiler;
+
+
+/// The maximal number of compiled bodies to keep
+const CACHE_SIZE: usize = 1024;
+
+thread_local!(static CACHE: RefCell<HashMap<(usize, Vec<Symbol>), (Rc<Vec<LVal>>, Rc<Code>)>>
+              = RefCell::new(HashMap::new()));
+
+
+/// Compile a function body or a branch, reusing earlier results
+///
+/// The values are kept alive by the cache, so their address identifies them.
+fn compile_shared(values: &Rc<Vec<LVal>>, locals: Vec<Symbol>) -> Rc<Code> {
+    let key = (&**values as *const Vec<LVal> as usize, locals);
+
+    let cached = CACHE.with(|cache| {
+        cache.borrow().get(&key).map(|&(_, ref code)| code.clone())
+    });
+
+    if let Some(code) = cached {
+        return code
+    }
+
+    let code = Rc::new(compile(&LVal::SExpr(values.clone(), Span::none()), &key.1));
+
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.len() >= CACHE_SIZE {
+            cache.clear();
+        }
+
+        cache.insert(key, (values.clone(), code.clone()));
+    });
+
+    code
+}
+
+
+/// Get the current values of the local variables of `code`
+fn load_slots(code: &Code, env: &LEnv) -> Vec<LVal> {
+    code.locals.iter().map(|&name| env.get(name)).collect()
+}
+
+
+/// Code being run
+struct Frame {
+    code: Rc<Code>,
+    pc: usize,
+    env: LEnv,
+
+    /// The values of the local variables
+    slots: Vec<LVal>,
+
+    /// Whether the frame runs in the environment of the frame below, like
+    /// the branches of `if` do
+    shares_env: bool,
+
+    /// The depth of the call stack when the frame was entered
+    depth: usize,
+
+    /// Where the code has been called, used for S-Exprs without a location
+    location: Option<SourceLocation>
+}
+
+
+struct Machine {
+    values: Vec<LVal>,
+    frames: Vec<Frame>
+}
+
+impl Machine {
+    fn top(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+
+    /// The location of a call site in the current frame
+    fn location(&self, site: &CallSite) -> Option<SourceLocation> {
+        match site.span.0 {
+            Some(ref location) => Some(location.clone()),
+            None => self.frames[self.frames.len() - 1].location.clone()
+        }
+    }
+
+    /// Push the result of a call site, errors get its location
+    fn push_result(&mut self, value: LVal, site: &CallSite) {
+        let value = match value {
+            LVal::Err(mut err) => {
+                if err.location.is_none() {
+                    err.location = self.location(site);
+                }
+
+                if err.backtrace.is_none() {
+                    err.backtrace = Some(stack::backtrace());
+                }
+
+                LVal::Err(err)
+            },
+            value => value
+        };
+
+        self.values.push(value);
+    }
+
+    /// Run code in a new frame or, for tail calls, in place of the current
+    /// code
+    fn enter(&mut self, code: Rc<Code>, env: LEnv, shares_env: bool,
+             call: Option<StackFrame>, location: Option<SourceLocation>, tail: bool) {
+        let slots = load_slots(&code, &env);
+
+        if tail {
+            let frame = self.top();
+
+            if let Some(call) = call {
+                // A tail call replaces the frame of the current function
+                if stack::depth() > frame.depth {
+                    stack::replace_top(call);
+                } else {
+                    stack::push(call);
+                }
+            }
+
+            frame.code = code;
+            frame.pc = 0;
+            frame.env = env;
+            frame.slots = slots;
+            frame.location = location;
+        } else {
+            let depth = stack::depth();
+
+            if let Some(call) = call {
+                stack::push(call);
+            }
+
+            self.frames.push(Frame {
+                code: code,
+                pc: 0,
+                env: env,
+                slots: slots,
+                shares_env: shares_env,
+                depth: depth,
+                location: location
+            });
+        }
+    }
+
+    /// Call a function with evaluated arguments
+    fn apply(&mut self, func: LVal, args: Vec<LVal>, site: &CallSite) {
+        let location = self.location(site);
+
+        match func {
+            LVal::Function { env: fenv, mut formals, body } => {
+                let mut lenv = fenv.copy();
+
+                if let Err(err) = bind_arguments(&mut lenv, formals.make_unique(), &body, args) {
+                    return self.push_result(err, site)
+                }
+
+                if formals.len() > 0 {
+                    // Partially applied
+                    return self.values.push(LVal::Function {
+                        env: lenv,
+                        formals: formals,
+                        body: body
+                    })
+                }
+
+                // Frames live on the heap, but are limited like `eval`'s nesting
+                if !site.tail && self.frames.len() >= stack::max_depth() {
+                    return self.push_result(depth_exceeded(&site.name), site)
+                }
+
+                let code = compile_shared(&body, lenv.local_names());
+                let call = StackFrame::new(&site.name, location.clone());
+                self.enter(code, lenv, false, Some(call), location, site.tail);
+            },
+
+            LVal::Builtin(LBuiltin(f, flags)) => {
+                let result = call_builtin(f, &mut self.top().env, args);
+
+                if flags & WRITES_ENV != 0 {
+                    let frame = self.top();
+                    frame.slots = load_slots(&frame.code, &frame.env);
+                }
+
+                match result {
+                    LVal::TailCall(body) => {
+                        // Continue with the body in the current environment
+                        let (locals, env) = {
+                            let frame = self.top();
+                            (frame.code.locals.clone(), frame.env.clone())
+                        };
+
+                        let code = compile_shared(&body, locals);
+                        self.enter(code, env, true, None, location, site.tail);
+                    },
+                    value => self.push_result(value, site)
+                }
+            },
+
+            // Like in `eval::apply`
+            LVal::Sym(name) => {
+                let value = self.top().env.get(name);
+
+                match value {
+                    builtin @ LVal::Builtin(..) => self.apply(builtin, args, site),
+                    _ => self.push_result(LVal::err_kind("type-error", format!(
+                        "first element is not a function: {}", name)), site)
+                }
+            },
+
+            first => self.push_result(LVal::err_kind("type-error", format!(
+                "first element is not a function but {}: `{}`", first.type_name(), first)), site)
+        }
+    }
+
+    /// Expand a macro call and run the expansion
+    fn expand(&mut self, mac: LVal, site: &CallSite) {
+        let args = site.args.iter()
+            .map(|val| LVal::qexpr_of(vec![val.clone()]))
+            .collect();
+
+        let expansion = match expand_macro(mac, args) {
+            err @ LVal::Err(..) => return self.push_result(err, site),
+            LVal::QExpr(code) => LVal::SExpr(code.to_shared(), site.span.clone()),
+            code => code
+        };
+
+        let (locals, env) = {
+            let frame = self.top();
+            (frame.code.locals.clone(), frame.env.clone())
+        };
+
+        // Expansions are new every time, so there's no use in caching them
+        let code = Rc::new(compile(&expansion, &locals));
+        let location = self.location(site);
+        self.enter(code, env, true, None, location, site.tail);
+    }
+
+    fn run(&mut self) -> LVal {
+        loop {
+            let (op, code) = {
+                let frame = self.top();
+                frame.pc += 1;
+                (frame.code.ops[frame.pc - 1], frame.code.clone())
+            };
+
+            match op {
+                Op::Const(constant) => self.values.push(code.constants[constant].clone()),
+                Op::Local(slot) => {
+                    let value = self.top().slots[slot].clone();
+                    self.values.push(value);
+                },
+                Op::Global(name) => {
+                    let value = self.top().env.get(name);
+                    self.values.push(value);
+                },
+                Op::Macro(site, end) => {
+                    let is_macro = match self.values.last() {
+                        Some(&LVal::Macro { .. }) => true,
+                        _ => false
+                    };
+
+                    if is_macro {
+                        let mac = self.values.pop().unwrap();
+                        self.top().pc = end;
+
+                        // Counts as evaluating the S-Expr, like in `eval_sexpr`
+                        if !fuel::consume() {
+                            self.push_result(fuel::out_of_fuel(), &code.sites[site]);
+                            continue
+                        }
+
+                        self.expand(mac, &code.sites[site]);
+                    }
+                },
+                Op::Call(argc, site) => {
+                    if !fuel::consume() {
+                        let start = self.values.len() - argc - 1;
+                        self.values.truncate(start);
+                        self.push_result(fuel::out_of_fuel(), &code.sites[site]);
+                        continue
+                    }
+
+                    let start = self.values.len() - argc - 1;
+                    let mut values = self.values.split_off(start);
+
+                    // Pass on the first error
+                    if let Some(i) = values.iter().position(|value| lval_is!(*value, err)) {
+                        let err = values.swap_remove(i);
+                        self.push_result(err, &code.sites[site]);
+                        continue
+                    }
+
+                    if argc == 0 {
+                        self.values.push(values.pop().unwrap());
+                        continue
+                    }
+
+                    let func = values.remove(0);
+                    self.apply(func, values, &code.sites[site]);
+                },
+                Op::Return => {
+                    let value = self.values.pop().unwrap();
+                    let frame = self.frames.pop().unwrap();
+
+                    // Leave the function entered by the frame, if any
+                    stack::truncate(frame.depth);
+
+                    if self.frames.len() == 0 {
+                        return value
+                    }
+
+                    // The frame may have defined variables in our environment
+                    if frame.shares_env {
+                        let parent = self.top();
+                        parent.slots = load_slots(&parent.code, &parent.env);
+                    }
+
+                    self.values.push(value);
+                }
+            }
+        }
+    }
+}
+
+
+/// Compile and run an expression
+pub fn run(env: &mut LEnv, node: LVal) -> LVal {
+    let code = Rc::new(compile(&node, &[]));
+
+    let mut machine = Machine {
+        values: vec![],
+        frames: vec![Frame {
+            code: code,
+            pc: 0,
+            env: env.clone(),
+            slots: vec![],
+            shares_env: false,
+            depth: stack::depth(),
+            location: None
+        }]
+    };
+
+    machine.run()
+}
+
+
+// --- Tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use eval::eval;
+    use lval::LVal;
+    use testing::{env, parse};
+    use super::run;
+
+    /// Run code with the evaluator and the virtual machine and check that
+    /// they agree
+    fn compare(lines: &[&str]) -> LVal {
+        let mut eval_env = env(true);
+        let mut vm_env = env(true);
+
+        let mut result = LVal::sexpr();
+        for line in lines {
+            let expected = eval(&mut eval_env, parse(*line));
+            result = run(&mut vm_env, parse(*line));
+
+            assert_eq!(result, expected);
+        }
+
+        result
+    }
+
+    #[test]
+    fn vm_arithmetic() {
+        assert_eq!(compare(&["(+ 1 (* 2 3) (- 4))"]), LVal::int(3));
+    }
+
+    #[test]
+    fn vm_functions() {
+        assert_eq!(
+            compare(&[
+                "(fun {fact n} {if (== n 0) {1} {* n (fact (- n 1))}})",
+                "(def {adder} (\\ {x} {\\ {y} {+ x y}}))",
+                "(def {add5} (adder 5))",
+                "(fun {rest x ... xs} {xs})",
+                "(list (fact 10) (add5 3) (rest 1 2 3) (map (\\ {x} {* x x}) {1 2 3}))"
+            ]),
+            LVal::qexpr_of(vec![
+                LVal::int(3628800),
+                LVal::int(8),
+                LVal::qexpr_of(vec![LVal::int(2), LVal::int(3)]),
+                LVal::qexpr_of(vec![LVal::int(1), LVal::int(4), LVal::int(9)])
+            ])
+        )
+    }
+
+    #[test]
+    fn vm_tail_calls() {
+        assert_eq!(
+            compare(&[
+                "(fun {count n acc} {if (== n 0) {acc} {count (- n 1) (+ acc 1)}})",
+                "(count 100000 0)"
+            ]),
+            LVal::int(100000)
+        )
+    }
+
+    #[test]
+    fn vm_local_assignment() {
+        assert_eq!(
+            compare(&[
+                "(fun {f x} {do (= {x} (* x 2)) (+ x 1)})",
+                "(f 5)"
+            ]),
+            LVal::int(11)
+        )
+    }
+
+    #[test]
+    fn vm_macros() {
+        assert_eq!(
+            compare(&[
+                "(fun {sign x} {cond {(< x 0) (- 1)} {(> x 0) 1} {otherwise 0}})",
+                "(list (sign 5) (sign 0) (when (> 2 1) (+ 1 1)) (unless true 1))"
+            ]),
+            LVal::qexpr_of(vec![LVal::int(1), LVal::int(0), LVal::int(2), LVal::sexpr()])
+        )
+    }
+
+    #[test]
+    fn vm_errors() {
+        compare(&["(+ 1 undefined)"]);
+        compare(&["(fun {f x} {x})", "(f 1 2)"]);
+        assert_eq!(
+            compare(&["(try {/ 1 0} (\\ {e} {error-kind e}))"]),
+            LVal::qexpr_of(vec![LVal::sym("division-by-zero")])
+        );
+        compare(&["(1 2 3)"]);
+    }
+}